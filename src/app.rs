@@ -1,4 +1,4 @@
-use std::{io::stdout, sync::mpsc, thread};
+use std::{io::stdout, panic, sync::mpsc, thread};
 
 use crossterm::{
     event::{self, Event, KeyCode},
@@ -17,50 +17,102 @@ use tui::{
 
 use crate::{
     config::Config,
-    media::player::{MusicPlayer, Player},
+    handler::handle_keyboard_event,
+    media::{
+        engine::{Command, Engine, Event as PlayerEvent},
+        output::OutputBackend,
+    },
+    stations::StationFile,
     ui::{
+        device::{draw_device_picker, DevicePicker},
+        effects::Visualizer,
+        equalizer::EqualizerController,
         fs::{draw_fs_tree, FsExplorer},
         music_board::{draw_music_board, MusicController},
-        radio::RadioExplorer,
+        play_list::draw_save_playlist,
+        radio::{draw_radio_list, draw_station_editor, RadioExplorer},
         EventType,
-    }, handler::handle_keyboard_event,
+    },
 };
 
 #[derive(PartialEq)]
 pub enum ActiveModules {
     Fs,
-    PlayList
+    PlayList,
+    Equalizer,
+    Radio,
+    StationEditor,
+    SavePlaylist,
+    Devices,
 }
 
 pub struct App {
-    pub player: MusicPlayer,
+    pub player: Engine,
+    /// The entry picked in the playlist panel, which needn't be the one playing.
+    pub play_list_index: ListState,
+    /// The file name typed to save the playlist as, while it is asked for.
+    pub playlist_name: Option<String>,
     pub radio_fs: RadioExplorer,
     pub fs: FsExplorer,
     pub music_controller: MusicController,
+    pub equalizer: EqualizerController,
+    pub visualizer: Visualizer,
+    pub device_picker: DevicePicker,
     pub active_modules: ActiveModules,
-    config: Config,
+    pub config: Config,
     msg: String,
 }
 
 impl App {
-    pub fn new() -> Option<Self> {
-        Some(Self {
+    /// `output` overrides the backend from the config for this run only.
+    pub fn new(output: Option<OutputBackend>) -> Option<Self> {
+        let config = Config::load();
+        let backend = output.unwrap_or_else(|| config.output.clone());
+        let device = match backend {
+            OutputBackend::Cpal => config.output_device.clone(),
+            _ => None,
+        };
+        let mut play_list_index = ListState::default();
+        play_list_index.select(Some(0));
+        let (stations, warnings) = StationFile::load();
+        let msg = warnings
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| "Welcome to wy-media".to_string());
+        let app = Self {
             fs: FsExplorer::default(Some(|err| {
                 eprintln!("{}", err);
             }))
             .ok()?,
-            player: MusicPlayer::new(),
-            radio_fs: RadioExplorer::new(),
+            player: Engine::spawn(backend, device, config.tick_gap)?,
+            play_list_index,
+            playlist_name: None,
+            radio_fs: RadioExplorer::new(stations),
             music_controller: MusicController {
                 state: ListState::default(),
             },
+            equalizer: EqualizerController { band: 0 },
+            visualizer: Visualizer::new(),
+            device_picker: DevicePicker::new(),
             active_modules: ActiveModules::Fs,
-            config: Config::default(),
-            msg: "Welcome to wy-media".to_string(),
-        })
+            config,
+            msg,
+        };
+        app.player.send(Command::SetCrossfade(app.config.crossfade));
+        app.player
+            .send(Command::SetReplayGain(app.config.replay_gain));
+        app.player.send(Command::SetEqualizer(app.config.equalizer));
+        Some(app)
     }
 
     pub fn run(&mut self) -> Result<(), Error> {
+        // put the terminal back before a panic message gets printed into raw mode
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = disable_raw_mode();
+            let _ = execute!(std::io::stdout(), LeaveAlternateScreen);
+            hook(info);
+        }));
         let mut stdout = stdout();
         execute!(stdout, EnterAlternateScreen)?;
         let backend = CrosstermBackend::new(stdout);
@@ -72,14 +124,18 @@ impl App {
         let tick = self.config.tick_gap.clone();
         thread::spawn(move || loop {
             thread::sleep(tick);
-            let _ = sd.send(EventType::Player);
             let _ = sd.send(EventType::Radio);
         });
         loop {
             if event::poll(self.config.refresh_rate)? {
                 if let Event::Key(key) = event::read()? {
                     match key.code {
-                        KeyCode::Char('q') | KeyCode::Char('Q') => {
+                        KeyCode::Char('q') | KeyCode::Char('Q')
+                            if !matches!(
+                                self.active_modules,
+                                ActiveModules::StationEditor | ActiveModules::SavePlaylist
+                            ) =>
+                        {
                             // todo empty cache function
                             break;
                         }
@@ -90,11 +146,13 @@ impl App {
                 }
             }
             thread::sleep(self.config.refresh_rate);
+            self.update_player();
             self.draw_frame(&mut terminal)?;
             if let Ok(event) = rd.try_recv() {
                 self.handle_events(event);
             }
         }
+        let _ = self.config.save();
         disable_raw_mode()?;
         execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
         terminal.show_cursor()?;
@@ -103,11 +161,7 @@ impl App {
 
     fn handle_events(&mut self, event: EventType) {
         match event {
-            EventType::Player => {
-                let player = &mut self.player;
-                player.tick();
-            }
-            EventType::Radio => {}
+            EventType::Radio => self.radio_fs.update(self.player.state()),
         }
     }
 
@@ -151,6 +205,42 @@ impl App {
         self.msg = String::from(msg);
     }
 
+    /// Picks up the state the engine published and what happened since.
+    fn update_player(&mut self) {
+        self.player.refresh();
+        while let Some(event) = self.player.try_event() {
+            match event {
+                PlayerEvent::Added => self.set_msg("Start playing"),
+                PlayerEvent::OutputChanged(device) => {
+                    let name = device
+                        .clone()
+                        .unwrap_or_else(|| "System default".to_string());
+                    self.config.output = OutputBackend::Cpal;
+                    self.config.output_device = device;
+                    let _ = self.config.save();
+                    self.set_msg(&format!("Output: {}", name));
+                }
+                PlayerEvent::Recording(Some(path)) => {
+                    self.set_msg(&format!("Recording to {}", path.display()))
+                }
+                PlayerEvent::Recording(None) => self.set_msg("Recording stopped"),
+                PlayerEvent::PlaylistLoaded(path) => {
+                    self.play_list_index.select(Some(0));
+                    self.set_msg(&format!("Playing {}", path.display()));
+                }
+                PlayerEvent::PlaylistSaved(path) => {
+                    self.fs.refresh();
+                    self.set_msg(&format!("Playlist saved to {}", path.display()));
+                }
+                PlayerEvent::Error(err) => {
+                    // whatever was being tuned in to won't start now
+                    self.radio_fs.connecting = None;
+                    self.set_msg(&err.to_string());
+                }
+            }
+        }
+    }
+
     pub fn draw_body<B>(&mut self, frame: &mut Frame<B>, area: Rect) -> Result<(), Error>
     where
         B: Backend,
@@ -160,8 +250,23 @@ impl App {
             .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
             .split(area);
 
-        draw_fs_tree(self, frame, main_layout[0]);
+        let side_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+            .split(main_layout[0]);
+
+        draw_fs_tree(self, frame, side_layout[0]);
+        draw_radio_list(self, frame, side_layout[1]);
         draw_music_board(self, frame, main_layout[1]);
+        if self.active_modules == ActiveModules::Devices {
+            draw_device_picker(self, frame, area);
+        }
+        if self.active_modules == ActiveModules::StationEditor {
+            draw_station_editor(self, frame, area);
+        }
+        if self.active_modules == ActiveModules::SavePlaylist {
+            draw_save_playlist(self, frame, area);
+        }
         Ok(())
     }
 }
@@ -1,8 +1,26 @@
-use std::time::Duration;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::media::{equalizer::BANDS, output::OutputBackend, replay_gain::ReplayGainMode};
+
+pub const MAX_CROSSFADE: Duration = Duration::from_secs(12);
 
 pub struct Config {
     pub refresh_rate: Duration,
     pub tick_gap: Duration,
+    pub crossfade: Duration,
+    pub replay_gain: ReplayGainMode,
+    /// Equalizer band gains in dB, low to high.
+    pub equalizer: [f32; BANDS],
+    pub output: OutputBackend,
+    /// The cpal output device as "<host>/<device>", `None` for the system default.
+    pub output_device: Option<String>,
+    /// Where radio recordings are saved.
+    pub record_dir: PathBuf,
 }
 
 impl Config {
@@ -10,6 +28,94 @@ impl Config {
         Self {
             refresh_rate: Duration::from_millis(50),
             tick_gap: Duration::from_millis(100),
+            crossfade: Duration::from_secs(0),
+            replay_gain: ReplayGainMode::Off,
+            equalizer: [0.0; BANDS],
+            output: OutputBackend::Cpal,
+            output_device: None,
+            record_dir: dirs::audio_dir()
+                .or_else(dirs::home_dir)
+                .unwrap_or_default()
+                .join("Wy-Media"),
+        }
+    }
+
+    fn path() -> Option<PathBuf> {
+        let mut config_dir = dirs::config_dir()?;
+        config_dir.push("Wy-Media");
+        config_dir.push("config.ini");
+        Some(config_dir)
+    }
+
+    /// The defaults, overridden by whatever `config.ini` next to `radio.ini` sets.
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        let f = match Self::path().and_then(|path| File::open(path).ok()) {
+            Some(f) => f,
+            None => return config,
+        };
+        for line in BufReader::new(f).lines().map_while(Result::ok) {
+            let (key, value) = match line.trim().split_once(' ') {
+                Some((key, value)) => (key, value.trim()),
+                None => continue,
+            };
+            match key {
+                "crossfade" => {
+                    if let Ok(secs) = value.parse() {
+                        config.crossfade = Duration::from_secs(secs).min(MAX_CROSSFADE);
+                    }
+                }
+                "replay_gain" => {
+                    config.replay_gain = match value {
+                        "track" => ReplayGainMode::Track,
+                        "album" => ReplayGainMode::Album,
+                        _ => ReplayGainMode::Off,
+                    }
+                }
+                "equalizer" => {
+                    for (band, gain) in value.split(' ').filter_map(|g| g.parse().ok()).enumerate()
+                    {
+                        if band < BANDS {
+                            config.equalizer[band] = gain;
+                        }
+                    }
+                }
+                "output" => {
+                    if let Some(output) = OutputBackend::parse(value) {
+                        config.output = output;
+                    }
+                }
+                "output_device" => config.output_device = Some(value.to_string()),
+                "record_dir" => config.record_dir = PathBuf::from(value),
+                _ => {}
+            }
+        }
+        config
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut f = File::create(path)?;
+        writeln!(f, "crossfade {}", self.crossfade.as_secs())?;
+        let replay_gain = match self.replay_gain {
+            ReplayGainMode::Off => "off",
+            ReplayGainMode::Track => "track",
+            ReplayGainMode::Album => "album",
+        };
+        writeln!(f, "replay_gain {}", replay_gain)?;
+        let gains: Vec<String> = self.equalizer.iter().map(|g| g.to_string()).collect();
+        writeln!(f, "equalizer {}", gains.join(" "))?;
+        writeln!(f, "output {}", self.output)?;
+        if let Some(device) = &self.output_device {
+            writeln!(f, "output_device {}", device)?;
         }
+        writeln!(f, "record_dir {}", self.record_dir.display())?;
+        Ok(())
     }
 }
@@ -0,0 +1,37 @@
+use crossterm::event::KeyCode;
+
+use crate::{
+    app::{ActiveModules, App},
+    media::engine::Command,
+};
+
+pub fn handle_device_picker(app: &mut App, key: KeyCode) -> bool {
+    let picker = &mut app.device_picker;
+    // the system default entry comes before the devices
+    let len = picker.devices.len() + 1;
+    match key {
+        KeyCode::Down => {
+            if let Some(selected) = picker.index.selected() {
+                picker.index.select(Some((selected + 1) % len));
+            }
+            true
+        }
+        KeyCode::Up => {
+            if let Some(selected) = picker.index.selected() {
+                picker.index.select(Some((selected + len - 1) % len));
+            }
+            true
+        }
+        KeyCode::Enter => {
+            // the config is saved once the engine reports the switch
+            app.player.send(Command::SetOutputDevice(picker.selected()));
+            app.active_modules = ActiveModules::Fs;
+            true
+        }
+        KeyCode::Esc => {
+            app.active_modules = ActiveModules::Fs;
+            true
+        }
+        _ => false,
+    }
+}
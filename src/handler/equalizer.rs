@@ -0,0 +1,56 @@
+use crossterm::event::KeyCode;
+
+use crate::{
+    app::App,
+    media::{
+        engine::Command,
+        equalizer::{BANDS, MAX_GAIN, PRESETS},
+    },
+    ui::equalizer::preset_index,
+};
+
+pub fn handle_equalizer(app: &mut App, key: KeyCode) -> bool {
+    let band = app.equalizer.band;
+    match key {
+        KeyCode::Up => {
+            app.equalizer.band = (band + BANDS - 1) % BANDS;
+            true
+        }
+        KeyCode::Down => {
+            app.equalizer.band = (band + 1) % BANDS;
+            true
+        }
+        KeyCode::Left => {
+            let mut gains = app.config.equalizer;
+            gains[band] -= 1.0;
+            set_equalizer(app, gains);
+            true
+        }
+        KeyCode::Right => {
+            let mut gains = app.config.equalizer;
+            gains[band] += 1.0;
+            set_equalizer(app, gains);
+            true
+        }
+        KeyCode::Char('p') | KeyCode::Char('P') => {
+            let next = preset_index(&app.config.equalizer).map_or(0, |index| index + 1);
+            let (name, gains) = PRESETS[next % PRESETS.len()];
+            set_equalizer(app, gains);
+            app.set_msg(&format!("EQ preset: {}", name));
+            true
+        }
+        KeyCode::Char('0') => {
+            set_equalizer(app, PRESETS[0].1);
+            app.set_msg("EQ reset");
+            true
+        }
+        _ => false,
+    }
+}
+
+fn set_equalizer(app: &mut App, gains: [f32; BANDS]) {
+    // the same clamping the player does, so the config matches what is heard
+    let gains = gains.map(|gain| gain.clamp(-MAX_GAIN, MAX_GAIN));
+    app.player.send(Command::SetEqualizer(gains));
+    app.config.equalizer = gains;
+}
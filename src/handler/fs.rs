@@ -1,6 +1,7 @@
 use std::{
     cmp::{max, min},
     env::{current_dir, set_current_dir},
+    path::Path,
 };
 
 use crossterm::event::KeyCode;
@@ -8,8 +9,8 @@ use crossterm::event::KeyCode;
 use crate::{
     app::{ActiveModules, App},
     media::{
+        engine::Command,
         media::{Media, Source},
-        player::Player,
     },
 };
 
@@ -39,31 +40,32 @@ fn add_media_to_player(app: &mut App, once: bool) -> bool {
             true
         } else {
             let entry = &fse.files[selected - fse.dirs.len() - 1];
-            let mut res = app.player.add_to_list(
+            if is_playlist(&entry.path()) {
+                app.player.send(Command::LoadPlaylist(entry.path()));
+                return true;
+            }
+            // the whole path, as the explorer moves the working directory
+            app.player.send(Command::Add(
                 Media {
-                    src: Source::Local(entry.file_name().to_string_lossy().to_string()),
+                    src: Source::Local(entry.path().to_string_lossy().to_string()),
                 },
                 once,
-            );
+            ));
             if once {
                 for i in selected - fse.dirs.len()..fse.files.len() {
                     let entry = &fse.files[i];
-                    res = app.player.add_to_list(
+                    if is_playlist(&entry.path()) {
+                        continue;
+                    }
+                    app.player.send(Command::Add(
                         Media {
-                            src: Source::Local(entry.file_name().to_string_lossy().to_string()),
+                            src: Source::Local(entry.path().to_string_lossy().to_string()),
                         },
                         once,
-                    );
+                    ));
                 }
             }
-            if !res {
-                let msg = format!("Open failed: {}", entry.file_name().to_str().unwrap());
-                app.set_msg(&msg);
-            } else {
-                let msg = format!("Start playing");
-                app.set_msg(&msg);
-            }
-            res
+            true
         }
     } else {
         fse.index.select(Some(0));
@@ -71,6 +73,12 @@ fn add_media_to_player(app: &mut App, once: bool) -> bool {
     }
 }
 
+pub(super) fn is_playlist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"))
+}
+
 pub fn handle_fs(app: &mut App, key: KeyCode) -> bool {
     if app.active_modules != ActiveModules::Fs {
         return false;
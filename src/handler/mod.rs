@@ -1,23 +1,40 @@
+mod device;
+mod equalizer;
 mod fs;
 mod music_controller;
 mod player;
+mod radio;
 
 use crossterm::event::KeyCode;
 
 use self::{
+    device::handle_device_picker,
+    equalizer::handle_equalizer,
     fs::handle_fs,
-    music_controller::{handle_music_controller, handle_playlist},
+    music_controller::{handle_music_controller, handle_playlist, handle_save_playlist},
     player::handle_player,
+    radio::{handle_radio, handle_station_editor},
 };
 
 use crate::app::{ActiveModules, App};
 
 pub fn handle_active_modules(app: &mut App, key: KeyCode) -> bool {
+    // the station form uses tab to move between its fields
+    if matches!(
+        app.active_modules,
+        ActiveModules::StationEditor | ActiveModules::SavePlaylist
+    ) {
+        return false;
+    }
     match key {
         KeyCode::Tab => {
             if app.active_modules == ActiveModules::Fs {
                 app.active_modules = ActiveModules::PlayList;
             } else if app.active_modules == ActiveModules::PlayList {
+                app.active_modules = ActiveModules::Equalizer;
+            } else if app.active_modules == ActiveModules::Equalizer {
+                app.active_modules = ActiveModules::Radio;
+            } else if app.active_modules == ActiveModules::Radio {
                 app.active_modules = ActiveModules::Fs;
             }
             return true;
@@ -55,5 +72,34 @@ pub fn handle_keyboard_event(app: &mut App, key: KeyCode) {
                 return;
             }
         }
+        ActiveModules::Equalizer => {
+            flag = handle_equalizer(app, key);
+            if flag {
+                return;
+            }
+        }
+        ActiveModules::Radio => {
+            flag = handle_radio(app, key);
+            if flag {
+                return;
+            }
+            flag = handle_player(app, key);
+            if flag {
+                return;
+            }
+            handle_music_controller(app, key);
+        }
+        ActiveModules::StationEditor => {
+            handle_station_editor(app, key);
+        }
+        ActiveModules::SavePlaylist => {
+            handle_save_playlist(app, key);
+        }
+        ActiveModules::Devices => {
+            flag = handle_device_picker(app, key);
+            if flag {
+                return;
+            }
+        }
     }
 }
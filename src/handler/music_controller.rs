@@ -1,25 +1,44 @@
-use std::cmp::{max, min};
+use std::{
+    cmp::{max, min},
+    path::{Path, PathBuf},
+};
+
+use super::fs::is_playlist;
 
 use crossterm::event::KeyCode;
 
 use crate::{
     app::{ActiveModules, App},
-    media::player::Player,
+    media::engine::Command,
 };
 
 pub fn handle_music_controller(app: &mut App, code: KeyCode) -> bool {
-    let player = &mut app.player;
+    let player = &app.player;
     match code {
         KeyCode::Char('s') | KeyCode::Char('S') => {
-            if player.is_playing() {
-                player.pause();
+            if player.state().is_playing {
+                player.send(Command::Pause);
             } else {
-                player.resume();
+                player.send(Command::Resume);
             }
             true
         }
+        KeyCode::Char('b') | KeyCode::Char('B') => {
+            player.send(Command::Previous);
+            true
+        }
         KeyCode::Char('n') | KeyCode::Char('N') => {
-            player.next();
+            player.send(Command::Next);
+            true
+        }
+        KeyCode::Char('z') | KeyCode::Char('Z') => {
+            let shuffle = !player.state().shuffle;
+            player.send(Command::SetShuffle(shuffle));
+            true
+        }
+        KeyCode::Char('l') | KeyCode::Char('L') => {
+            let repeat = player.state().repeat.cycle();
+            player.send(Command::SetRepeat(repeat));
             true
         }
         _ => false,
@@ -31,50 +50,92 @@ pub fn handle_playlist(app: &mut App, key: KeyCode) -> bool {
         return false;
     }
 
-    let playlist = &mut app.player.play_list;
-    let len = playlist.lists.len() - 1;
+    let playlist = &mut app.play_list_index;
+    let len = app.player.state().play_list.len().saturating_sub(1);
     match key {
         KeyCode::Down => {
-            if let Some(selected) = playlist.index.selected() {
+            if let Some(selected) = playlist.selected() {
                 if selected == len {
-                    playlist.index.select(Some(0));
+                    playlist.select(Some(0));
                 } else {
-                    playlist.index.select(Some(min(len, selected + 1)));
+                    playlist.select(Some(min(len, selected + 1)));
                 }
                 return true;
             }
         }
         KeyCode::Up => {
-            if let Some(selected) = playlist.index.selected() {
+            if let Some(selected) = playlist.selected() {
                 if selected == 0 {
-                    playlist.index.select(Some(len));
+                    playlist.select(Some(len));
                 } else {
-                    playlist.index.select(Some(max(0, selected - 1)));
+                    playlist.select(Some(max(0, selected - 1)));
                 }
                 return true;
             }
         }
         KeyCode::Enter => {
-            if let Some(selected) = playlist.index.selected() {
+            if let Some(selected) = playlist.selected() {
                 play_selected_song(app, selected);
             }
         }
         KeyCode::Char('r') | KeyCode::Char('R') => {
-            if let Some(selected) = playlist.index.selected() {
+            if let Some(selected) = playlist.selected() {
                 remove_from_playlist(app, selected);
             }
         }
+        KeyCode::Char('w') | KeyCode::Char('W') => {
+            app.playlist_name = Some("playlist.m3u8".to_string());
+            app.active_modules = ActiveModules::SavePlaylist;
+            return true;
+        }
         _ => {}
     }
     false
 }
 
-fn play_selected_song(app: &mut App, selected: usize) -> bool {
-    let player = &mut app.player;
-    player.play_selected(selected)
+/// Types the name the playlist is saved as, in the explorer's folder.
+pub fn handle_save_playlist(app: &mut App, key: KeyCode) -> bool {
+    let name = match app.playlist_name.as_mut() {
+        Some(name) => name,
+        None => {
+            app.active_modules = ActiveModules::PlayList;
+            return false;
+        }
+    };
+    match key {
+        KeyCode::Esc => close_save_playlist(app),
+        KeyCode::Backspace => {
+            name.pop();
+        }
+        KeyCode::Char(c) => name.push(c),
+        KeyCode::Enter => {
+            let name = name.trim();
+            if name.is_empty() {
+                return true;
+            }
+            let mut path = Path::new(&app.fs.current_path).join(name);
+            if !is_playlist(&path) {
+                path = PathBuf::from(format!("{}.m3u8", path.display()));
+            }
+            app.player.send(Command::SavePlaylist(path));
+            close_save_playlist(app);
+        }
+        _ => return false,
+    }
+    true
+}
+
+fn close_save_playlist(app: &mut App) {
+    app.playlist_name = None;
+    app.active_modules = ActiveModules::PlayList;
+}
+
+fn play_selected_song(app: &mut App, selected: usize) {
+    app.player.send(Command::PlaySelected(selected));
 }
 
-fn remove_from_playlist(app: &mut App, selected: usize) -> bool {
-    let player = &mut app.player;
-    player.remove_from_playlist(selected)
+fn remove_from_playlist(app: &mut App, selected: usize) {
+    app.player.send(Command::Remove(selected));
+    // TODO: fix weird layout after removing from the play list
+    app.play_list_index.select(Some(0));
 }
@@ -1,21 +1,87 @@
+use std::time::Duration;
+
 use crossterm::event::KeyCode;
 
-use crate::{app::App, media::player::Player};
+use crate::{
+    app::{ActiveModules, App},
+    config::MAX_CROSSFADE,
+    media::engine::Command,
+};
 
 pub fn handle_player(app: &mut App, code: KeyCode) -> bool {
     match code {
         KeyCode::Char('-') => {
-            let volume = app.player.volume() - 0.05;
+            let volume = app.player.state().volume - 0.05;
             let new_volume = volume.max(0.0);
-            app.player.set_volume(new_volume);
+            app.player.send(Command::SetVolume(new_volume));
             true
         }
         KeyCode::Char('=') => {
-            let volume = app.player.volume() + 0.05;
+            let volume = app.player.state().volume + 0.05;
             let new_volume = volume.min(1.0);
-            app.player.set_volume(new_volume);
+            app.player.send(Command::SetVolume(new_volume));
+            true
+        }
+        KeyCode::Char(',') => {
+            app.player.send(Command::SeekBy(-10));
+            true
+        }
+        KeyCode::Char('.') => {
+            app.player.send(Command::SeekBy(10));
+            true
+        }
+        KeyCode::Char('<') => {
+            let rate = app.player.state().rate - 0.25;
+            app.player.send(Command::SetRate(rate));
+            true
+        }
+        KeyCode::Char('>') => {
+            let rate = app.player.state().rate + 0.25;
+            app.player.send(Command::SetRate(rate));
+            true
+        }
+        KeyCode::Char('g') | KeyCode::Char('G') => {
+            let mode = app.config.replay_gain.cycle();
+            app.config.replay_gain = mode;
+            app.player.send(Command::SetReplayGain(mode));
+            app.set_msg(&format!("ReplayGain: {}", mode.label()));
+            true
+        }
+        KeyCode::Char('[') => {
+            let crossfade = app.config.crossfade.saturating_sub(Duration::from_secs(1));
+            set_crossfade(app, crossfade);
+            true
+        }
+        KeyCode::Char(']') => {
+            let crossfade = (app.config.crossfade + Duration::from_secs(1)).min(MAX_CROSSFADE);
+            set_crossfade(app, crossfade);
+            true
+        }
+        KeyCode::Char('c') | KeyCode::Char('C') => {
+            let dir = match app.player.state().recording {
+                Some(_) => None,
+                None => Some(app.config.record_dir.clone()),
+            };
+            app.player.send(Command::Record(dir));
+            true
+        }
+        KeyCode::Char('o') | KeyCode::Char('O') => {
+            let current = app.player.state().output_device.as_deref();
+            app.device_picker.refresh(current);
+            app.active_modules = ActiveModules::Devices;
             true
         }
         _ => false,
     }
 }
+
+fn set_crossfade(app: &mut App, crossfade: Duration) {
+    app.config.crossfade = crossfade;
+    app.player.send(Command::SetCrossfade(crossfade));
+    let msg = if crossfade.is_zero() {
+        "Crossfade off".to_string()
+    } else {
+        format!("Crossfade {}s", crossfade.as_secs())
+    };
+    app.set_msg(&msg);
+}
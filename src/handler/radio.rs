@@ -0,0 +1,153 @@
+use crossterm::event::KeyCode;
+
+use crate::{
+    app::{ActiveModules, App},
+    media::engine::Command,
+    ui::radio::StationEditor,
+};
+
+pub fn handle_radio(app: &mut App, key: KeyCode) -> bool {
+    let radio = &mut app.radio_fs;
+    let stations = &mut radio.stations.stations;
+    let len = stations.len();
+    let selected = radio.index.selected().filter(|selected| *selected < len);
+    match key {
+        KeyCode::Down => {
+            if let Some(selected) = selected {
+                radio.index.select(Some((selected + 1) % len));
+            }
+            true
+        }
+        KeyCode::Up => {
+            if let Some(selected) = selected {
+                radio.index.select(Some((selected + len - 1) % len));
+            }
+            true
+        }
+        KeyCode::Enter => {
+            if let Some(station) = radio.selected().cloned() {
+                radio.connecting = Some(station.url.clone());
+                app.player.send(Command::PlayNow(station.media()));
+                app.set_msg(&format!("Tuning in to {}", station.name));
+            }
+            true
+        }
+        KeyCode::Char('a') | KeyCode::Char('A') => {
+            radio.editor = Some(StationEditor::new());
+            app.active_modules = ActiveModules::StationEditor;
+            true
+        }
+        KeyCode::Char('e') | KeyCode::Char('E') => {
+            if let Some(selected) = selected {
+                radio.editor = Some(StationEditor::edit(selected, &stations[selected]));
+                app.active_modules = ActiveModules::StationEditor;
+            }
+            true
+        }
+        KeyCode::Char('d') | KeyCode::Char('D') => {
+            if let Some(selected) = selected {
+                let station = stations.remove(selected);
+                radio
+                    .index
+                    .select(Some(selected.min(stations.len().saturating_sub(1))));
+                save_stations(app, &format!("Deleted {}", station.name));
+            }
+            true
+        }
+        KeyCode::Char('f') | KeyCode::Char('F') => {
+            if let Some(selected) = selected {
+                let station = &mut stations[selected];
+                station.favourite = !station.favourite;
+                let msg = if station.favourite {
+                    format!("{} is a favourite", station.name)
+                } else {
+                    format!("{} is no longer a favourite", station.name)
+                };
+                save_stations(app, &msg);
+            }
+            true
+        }
+        // shift moves the station instead of the selection
+        KeyCode::Char('K') => {
+            if let Some(selected) = selected.filter(|selected| *selected > 0) {
+                stations.swap(selected, selected - 1);
+                radio.index.select(Some(selected - 1));
+                save_stations(app, "Moved up");
+            }
+            true
+        }
+        KeyCode::Char('J') => {
+            if let Some(selected) = selected.filter(|selected| selected + 1 < len) {
+                stations.swap(selected, selected + 1);
+                radio.index.select(Some(selected + 1));
+                save_stations(app, "Moved down");
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Takes every key while the station form is open, typing included.
+pub fn handle_station_editor(app: &mut App, key: KeyCode) -> bool {
+    let editor = match app.radio_fs.editor.as_mut() {
+        Some(editor) => editor,
+        None => {
+            app.active_modules = ActiveModules::Radio;
+            return false;
+        }
+    };
+    match key {
+        KeyCode::Esc => close_editor(app),
+        KeyCode::Tab | KeyCode::Down => editor.field = (editor.field + 1) % editor.fields.len(),
+        KeyCode::BackTab | KeyCode::Up => {
+            editor.field = (editor.field + editor.fields.len() - 1) % editor.fields.len()
+        }
+        KeyCode::Backspace => {
+            editor.fields[editor.field].pop();
+        }
+        KeyCode::Char(c) => editor.fields[editor.field].push(c),
+        KeyCode::Enter => {
+            let mut station = match editor.station() {
+                Ok(station) => station,
+                Err(err) => {
+                    app.set_msg(err);
+                    return true;
+                }
+            };
+            let editing = editor.editing;
+            close_editor(app);
+            let radio = &mut app.radio_fs;
+            let stations = &mut radio.stations.stations;
+            let msg = match editing.filter(|index| *index < stations.len()) {
+                Some(index) => {
+                    let old = &mut stations[index];
+                    station.favourite = old.favourite;
+                    station.comments = std::mem::take(&mut old.comments);
+                    *old = station;
+                    "Station saved"
+                }
+                None => {
+                    stations.push(station);
+                    radio.index.select(Some(stations.len() - 1));
+                    "Station added"
+                }
+            };
+            save_stations(app, msg);
+        }
+        _ => return false,
+    }
+    true
+}
+
+fn close_editor(app: &mut App) {
+    app.radio_fs.editor = None;
+    app.active_modules = ActiveModules::Radio;
+}
+
+fn save_stations(app: &mut App, msg: &str) {
+    match app.radio_fs.stations.save() {
+        Ok(()) => app.set_msg(msg),
+        Err(err) => app.set_msg(&format!("Cannot save radio.ini: {}", err)),
+    }
+}
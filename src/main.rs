@@ -1,13 +1,34 @@
 use app::*;
+use media::output::OutputBackend;
 
 mod app;
 mod config;
 mod handler;
 mod media;
+mod stations;
 mod ui;
 
+const USAGE: &str = "usage: wy-media [--output cpal|null|wav:<file>]";
+
 fn main() {
-    let mut app = App::new().unwrap();
+    let mut output = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => match args.next().as_deref().and_then(OutputBackend::parse) {
+                Some(backend) => output = Some(backend),
+                None => {
+                    eprintln!("{}", USAGE);
+                    std::process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+    let mut app = App::new(output).unwrap();
     match app.run() {
         Ok(_) => {}
         Err(e) => {
@@ -0,0 +1,51 @@
+use rodio::{
+    cpal::{self, traits::HostTrait},
+    DeviceTrait,
+};
+
+/// An output device of one of the cpal hosts.
+pub struct OutputDevice {
+    pub host: String,
+    pub name: String,
+}
+
+impl OutputDevice {
+    /// The "<host>/<device>" form the device is remembered by in the config.
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.host, self.name)
+    }
+}
+
+/// Every output device of every host cpal was built with.
+pub fn list_devices() -> Vec<OutputDevice> {
+    let mut devices = vec![];
+    for host_id in cpal::available_hosts() {
+        let host = match cpal::host_from_id(host_id) {
+            Ok(host) => host,
+            Err(_) => continue,
+        };
+        if let Ok(outputs) = host.output_devices() {
+            for device in outputs {
+                if let Ok(name) = device.name() {
+                    devices.push(OutputDevice {
+                        host: host_id.name().to_string(),
+                        name,
+                    });
+                }
+            }
+        }
+    }
+    devices
+}
+
+/// Looks a device up by its `OutputDevice::id`.
+pub fn find_device(id: &str) -> Option<cpal::Device> {
+    cpal::available_hosts().into_iter().find_map(|host_id| {
+        let name = id.strip_prefix(host_id.name())?.strip_prefix('/')?;
+        cpal::host_from_id(host_id)
+            .ok()?
+            .output_devices()
+            .ok()?
+            .find(|device| device.name().is_ok_and(|n| n == name))
+    })
+}
@@ -0,0 +1,265 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use super::{
+    equalizer::BANDS,
+    error::PlayerError,
+    media::Media,
+    output::OutputBackend,
+    player::{MusicPlayer, PlayListItem, Player, RepeatMode},
+    replay_gain::ReplayGainMode,
+    spectrum::Spectrum,
+};
+
+/// What a frontend can ask the engine to do.
+pub enum Command {
+    Add(Media, bool),
+    /// Plays the media straight away, adding it to the playlist if need be.
+    PlayNow(Media),
+    PlaySelected(usize),
+    Remove(usize),
+    Next,
+    Previous,
+    Pause,
+    Resume,
+    SeekBy(i64),
+    SetVolume(f32),
+    SetRate(f32),
+    SetCrossfade(Duration),
+    SetReplayGain(ReplayGainMode),
+    SetEqualizer([f32; BANDS]),
+    SetShuffle(bool),
+    SetRepeat(RepeatMode),
+    SetOutputDevice(Option<String>),
+    /// Records the playing station into the directory, or stops recording.
+    Record(Option<PathBuf>),
+    /// Replaces the playlist with an M3U file's.
+    LoadPlaylist(PathBuf),
+    /// Writes the playlist to an M3U file.
+    SavePlaylist(PathBuf),
+    Quit,
+}
+
+/// Things that happened on the engine thread a frontend may want to tell the user.
+pub enum Event {
+    /// A file sent with `Command::Add` is on the playlist.
+    Added,
+    /// Playback moved to this output device, `None` being the system default.
+    OutputChanged(Option<String>),
+    /// Recording started in this file, or stopped.
+    Recording(Option<PathBuf>),
+    PlaylistLoaded(PathBuf),
+    PlaylistSaved(PathBuf),
+    Error(PlayerError),
+}
+
+/// A copy of the player's state, published after every command and tick.
+#[derive(Clone)]
+pub struct PlayerState {
+    pub play_list: Vec<PlayListItem>,
+    pub selected: usize,
+    /// Seconds played and the length of the track, as `Player::get_progress`.
+    pub progress: (f32, f32),
+    pub is_playing: bool,
+    pub has_track: bool,
+    pub has_output: bool,
+    /// The playing stream has run dry and waits for more to arrive.
+    pub buffering: bool,
+    /// The file the playing station is being recorded to.
+    pub recording: Option<PathBuf>,
+    /// Seconds a stream plays behind live, and seconds it can rewind, as
+    /// `MusicPlayer::time_shift`.
+    pub time_shift: (f32, f32),
+    pub volume: f32,
+    pub rate: f32,
+    pub shuffle: bool,
+    pub repeat: RepeatMode,
+    pub equalizer: [f32; BANDS],
+    pub output_device: Option<String>,
+}
+
+impl PlayerState {
+    fn of(player: &MusicPlayer) -> Self {
+        Self {
+            play_list: player.play_list.lists.clone(),
+            selected: player.selected_song_index(),
+            progress: player.get_progress(),
+            is_playing: player.is_playing(),
+            has_track: player.has_track(),
+            has_output: player.has_output(),
+            buffering: player.is_buffering(),
+            recording: player.recording(),
+            time_shift: player.time_shift(),
+            volume: player.volume(),
+            rate: player.rate(),
+            shuffle: player.is_shuffle(),
+            repeat: player.repeat(),
+            equalizer: player.equalizer(),
+            output_device: player.output_device().map(String::from),
+        }
+    }
+
+    pub fn playing_song(&self) -> Option<&PlayListItem> {
+        self.play_list.get(self.selected)
+    }
+}
+
+/// A `MusicPlayer` running on its own thread, so nothing the UI does can
+/// hold up the audio. The output lives on that thread too, as a cpal stream
+/// can't be moved between threads.
+pub struct Engine {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    shared: Arc<Mutex<PlayerState>>,
+    state: PlayerState,
+    spectrum: Arc<Spectrum>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Engine {
+    /// Starts the engine on `backend`, and on the cpal `device` if one is
+    /// given. The player is ticked every `tick_gap`.
+    pub fn spawn(
+        backend: OutputBackend,
+        device: Option<String>,
+        tick_gap: Duration,
+    ) -> Option<Self> {
+        let (commands, command_rx) = mpsc::channel();
+        let (event_tx, events) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut player = MusicPlayer::new(backend);
+            if device.is_some() {
+                if let Err(err) = player.set_output_device(device) {
+                    let _ = event_tx.send(Event::Error(err));
+                }
+            }
+            let shared = Arc::new(Mutex::new(PlayerState::of(&player)));
+            if ready_tx.send((shared.clone(), player.spectrum())).is_ok() {
+                run(player, command_rx, event_tx, shared, tick_gap);
+            }
+        });
+        let (shared, spectrum) = ready_rx.recv().ok()?;
+        let state = shared.lock().unwrap().clone();
+        Some(Self {
+            commands,
+            events,
+            shared,
+            state,
+            spectrum,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    /// Takes in the latest state the engine published.
+    pub fn refresh(&mut self) {
+        self.state = self.shared.lock().unwrap().clone();
+    }
+
+    /// The state as of the last `refresh`.
+    pub fn state(&self) -> &PlayerState {
+        &self.state
+    }
+
+    pub fn try_event(&self) -> Option<Event> {
+        self.events.try_recv().ok()
+    }
+
+    pub fn spectrum(&self) -> &Spectrum {
+        &self.spectrum
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.send(Command::Quit);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run(
+    mut player: MusicPlayer,
+    commands: Receiver<Command>,
+    events: Sender<Event>,
+    shared: Arc<Mutex<PlayerState>>,
+    tick_gap: Duration,
+) {
+    let mut last_tick = Instant::now();
+    loop {
+        match commands.recv_timeout(tick_gap.saturating_sub(last_tick.elapsed())) {
+            Ok(Command::Quit) | Err(RecvTimeoutError::Disconnected) => break,
+            Ok(command) => {
+                let event = match apply(&mut player, command) {
+                    Ok(event) => event,
+                    Err(err) => Some(Event::Error(err)),
+                };
+                if let Some(event) = event {
+                    let _ = events.send(event);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        if last_tick.elapsed() >= tick_gap {
+            last_tick = Instant::now();
+            if let Err(err) = player.tick() {
+                let _ = events.send(Event::Error(err));
+            }
+        }
+        *shared.lock().unwrap() = PlayerState::of(&player);
+    }
+}
+
+fn apply(player: &mut MusicPlayer, command: Command) -> Result<Option<Event>, PlayerError> {
+    match command {
+        Command::Add(media, once) => {
+            player.add_to_list(media, once)?;
+            return Ok(Some(Event::Added));
+        }
+        Command::PlayNow(media) => player.play_now(media)?,
+        Command::PlaySelected(index) => player.play_selected(index)?,
+        Command::Remove(index) => player.remove_from_playlist(index)?,
+        Command::Next => player.next()?,
+        Command::Previous => player.previous()?,
+        Command::Pause => player.pause(),
+        Command::Resume => player.resume()?,
+        Command::SeekBy(secs) => player.seek_by(secs)?,
+        Command::SetVolume(volume) => player.set_volume(volume),
+        Command::SetRate(rate) => player.set_rate(rate),
+        Command::SetCrossfade(crossfade) => player.set_crossfade(crossfade),
+        Command::SetReplayGain(mode) => player.set_replay_gain(mode),
+        Command::SetEqualizer(gains) => player.set_equalizer(gains),
+        Command::SetShuffle(shuffle) => player.set_shuffle(shuffle),
+        Command::SetRepeat(repeat) => player.set_repeat(repeat),
+        Command::SetOutputDevice(device) => {
+            player.set_output_device(device.clone())?;
+            return Ok(Some(Event::OutputChanged(device)));
+        }
+        Command::Record(dir) => {
+            let recording = player.record(dir)?;
+            return Ok(Some(Event::Recording(recording)));
+        }
+        Command::LoadPlaylist(path) => {
+            player.load_playlist(&path)?;
+            return Ok(Some(Event::PlaylistLoaded(path)));
+        }
+        Command::SavePlaylist(path) => {
+            player.save_playlist(&path)?;
+            return Ok(Some(Event::PlaylistSaved(path)));
+        }
+        Command::Quit => {}
+    }
+    Ok(None)
+}
@@ -0,0 +1,244 @@
+use std::{
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use rodio::Source;
+
+pub const BANDS: usize = 10;
+pub const FREQUENCIES: [f32; BANDS] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+pub const MAX_GAIN: f32 = 12.0;
+
+pub const PRESETS: &[(&str, [f32; BANDS])] = &[
+    ("Flat", [0.0; BANDS]),
+    (
+        "Bass Boost",
+        [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    ),
+    (
+        "Treble Boost",
+        [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0],
+    ),
+    (
+        "Vocal",
+        [-2.0, -2.0, -1.0, 1.0, 3.0, 3.0, 2.0, 1.0, 0.0, -1.0],
+    ),
+    ("Rock", [4.0, 3.0, 2.0, 0.0, -1.0, -1.0, 1.0, 2.0, 3.0, 4.0]),
+    ("Pop", [-1.0, 1.0, 3.0, 4.0, 3.0, 0.0, -1.0, -1.0, 1.0, 2.0]),
+    ("Jazz", [3.0, 2.0, 1.0, 2.0, -1.0, -1.0, 0.0, 1.0, 2.0, 3.0]),
+    (
+        "Classical",
+        [4.0, 3.0, 2.0, 1.0, -1.0, -1.0, 0.0, 2.0, 3.0, 4.0],
+    ),
+    (
+        "Loudness",
+        [6.0, 4.0, 0.0, 0.0, -2.0, 0.0, -1.0, -4.0, 4.0, 1.0],
+    ),
+];
+
+/// Band gains in dB shared between the player and every `Equalizer` in the sink.
+#[derive(Default)]
+pub struct EqSettings {
+    gains: Mutex<[f32; BANDS]>,
+    generation: AtomicU64,
+}
+
+impl EqSettings {
+    pub fn gains(&self) -> [f32; BANDS] {
+        *self.gains.lock().unwrap()
+    }
+
+    pub fn set_gains(&self, gains: [f32; BANDS]) {
+        *self.gains.lock().unwrap() = gains.map(|gain| gain.clamp(-MAX_GAIN, MAX_GAIN));
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A peaking biquad in direct form I, coefficients normalised by a0.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    fn peaking(frequency: f32, gain: f32, sample_rate: u32) -> Self {
+        // one octave per band
+        let q = std::f32::consts::SQRT_2;
+        let a = 10f32.powf(gain / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha / a;
+        Self {
+            b0: (1.0 + alpha * a) / a0,
+            b1: -2.0 * cos / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha / a) / a0,
+        }
+    }
+}
+
+pub struct Equalizer<S> {
+    inner: S,
+    settings: Arc<EqSettings>,
+    generation: u64,
+    channels: usize,
+    sample_rate: u32,
+    filters: Vec<Biquad>,
+    // x1, x2, y1, y2 per filter and channel
+    state: Vec<[f32; 4]>,
+    preamp: f32,
+    /// Whether every band is at 0 dB, when samples pass straight through.
+    flat: bool,
+    channel: usize,
+    counter: usize,
+}
+
+impl<S> Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, settings: Arc<EqSettings>) -> Self {
+        let mut eq = Self {
+            channels: inner.channels().max(1) as usize,
+            sample_rate: inner.sample_rate(),
+            inner,
+            settings,
+            generation: 0,
+            filters: vec![],
+            state: vec![],
+            preamp: 1.0,
+            flat: true,
+            channel: 0,
+            counter: 0,
+        };
+        let nyquist = eq.sample_rate as f32 / 2.0;
+        let bands = FREQUENCIES
+            .iter()
+            .filter(|frequency| **frequency < nyquist)
+            .count();
+        eq.state = vec![[0.0; 4]; bands * eq.channels];
+        eq.update();
+        eq
+    }
+
+    /// Recomputes the filters for the current gains. Every band below
+    /// Nyquist keeps its filter and its state, even at 0 dB where it passes
+    /// audio through unchanged, so a change while playing doesn't click.
+    fn update(&mut self) {
+        self.generation = self.settings.generation.load(Ordering::Relaxed);
+        let gains = self.settings.gains();
+        let bands = self.state.len() / self.channels;
+        self.filters = FREQUENCIES
+            .iter()
+            .zip(gains.iter())
+            .take(bands)
+            .map(|(frequency, gain)| Biquad::peaking(*frequency, *gain, self.sample_rate))
+            .collect();
+        self.flat = gains.iter().all(|gain| *gain == 0.0);
+        // leave headroom for the largest boost so it doesn't clip
+        let boost = gains.iter().fold(0f32, |max, gain| max.max(*gain));
+        self.preamp = 10f32.powf(-boost / 20.0);
+    }
+}
+
+impl<S> Iterator for Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut sample = self.inner.next()?;
+        if self.channel == 0 {
+            self.counter += 1;
+            if self.counter >= 1024 {
+                self.counter = 0;
+                if self.settings.generation.load(Ordering::Relaxed) != self.generation {
+                    self.update();
+                }
+            }
+        }
+        if self.flat {
+            // the filters would pass it unchanged, so only their state moves on
+            for i in 0..self.filters.len() {
+                let state = &mut self.state[i * self.channels + self.channel];
+                *state = [sample, state[0], sample, state[2]];
+            }
+        } else {
+            sample *= self.preamp;
+            for (i, filter) in self.filters.iter().enumerate() {
+                let state = &mut self.state[i * self.channels + self.channel];
+                let out = filter.b0 * sample + filter.b1 * state[0] + filter.b2 * state[1]
+                    - filter.a1 * state[2]
+                    - filter.a2 * state[3];
+                *state = [sample, state[0], out, state[2]];
+                sample = out;
+            }
+        }
+        self.channel = (self.channel + 1) % self.channels;
+        Some(sample)
+    }
+}
+
+impl<S> Source for Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::source::SineWave;
+
+    use super::*;
+
+    fn largest_step(samples: &[f32]) -> f32 {
+        samples
+            .windows(2)
+            .fold(0f32, |max, pair| max.max((pair[1] - pair[0]).abs()))
+    }
+
+    #[test]
+    fn gain_change_keeps_filter_state() {
+        let settings = Arc::new(EqSettings::default());
+        let mut gains = [0.0; BANDS];
+        gains[0] = 6.0;
+        settings.set_gains(gains);
+        let mut eq = Equalizer::new(SineWave::new(62.0), settings.clone());
+        let before: Vec<f32> = eq.by_ref().take(48_000).collect();
+        // same largest boost, so the preamp stays put and only the filters change
+        gains[1] = 6.0;
+        settings.set_gains(gains);
+        let after: Vec<f32> = eq.by_ref().take(48_000).collect();
+        let steady = largest_step(&before[24_000..]).max(largest_step(&after[24_000..]));
+        let step = largest_step(&after[..4096]);
+        assert!(step < steady * 1.05, "{} jumped to {}", steady, step);
+    }
+}
@@ -0,0 +1,41 @@
+use failure::Fail;
+use symphonia::core::errors::Error as DecodeError;
+
+/// Why the player couldn't do what it was asked.
+#[derive(Fail, Debug)]
+pub enum PlayerError {
+    /// The file opened but its audio could not be decoded.
+    #[fail(display = "Cannot decode {}: {}", _0, _1)]
+    Decode(String, String),
+    /// The file could not be read.
+    #[fail(display = "Cannot read {}: {}", _0, _1)]
+    Io(String, String),
+    /// The container or codec isn't one the decoder knows.
+    #[fail(display = "Unsupported format {}: {}", _0, _1)]
+    Unsupported(String, String),
+    /// The output could not be opened, or went away.
+    #[fail(display = "Audio device: {}", _0)]
+    Device(String),
+    #[fail(display = "No track {} in the playlist", _0)]
+    NoSuchTrack(usize),
+    #[fail(display = "Cannot record: {}", _0)]
+    Record(String),
+    #[fail(display = "Playlist: {}", _0)]
+    Playlist(String),
+}
+
+impl PlayerError {
+    /// Sorts an error from probing or opening the file `name`.
+    pub fn track(name: &str, err: DecodeError) -> Self {
+        let name = name.to_string();
+        match err {
+            DecodeError::IoError(err) => PlayerError::Io(name, err.to_string()),
+            DecodeError::Unsupported(msg) => PlayerError::Unsupported(name, msg.to_string()),
+            err => PlayerError::Decode(name, err.to_string()),
+        }
+    }
+
+    pub fn device(err: failure::Error) -> Self {
+        PlayerError::Device(err.to_string())
+    }
+}
@@ -0,0 +1,430 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use m3u8_rs::{AlternativeMediaType, MasterPlaylist, MediaPlaylist, Playlist};
+use symphonia::core::{errors::Error, formats::FormatReader};
+use url::Url;
+
+use super::{
+    stream::{agent, invalid, Chunks, StreamTap},
+    ts::{id3_title, strip_id3, AudioKind, TsDemuxer},
+};
+
+/// How many segments are fetched ahead of the one playing.
+const SEGMENTS_AHEAD: usize = 3;
+
+/// How far from the live edge to start, in segments, as the HLS spec advises.
+const LIVE_START: usize = 3;
+
+/// Opens the HLS stream at `url`, which may be a master or a media playlist,
+/// as a demuxer over the audio in its segments. Segments are fetched on a
+/// thread that keeps reloading a live playlist until the demuxer is dropped,
+/// passing the titles the playlist or the segments give and the audio to `tap`.
+/// This waits on the network for the first segment, so the player calls it
+/// off the engine thread.
+pub fn open(url: &str, tap: Arc<StreamTap>) -> Result<Box<dyn FormatReader>, Error> {
+    let url = Url::parse(url).map_err(invalid)?;
+    let mut fetcher = Fetcher::new(agent(), url, tap)?;
+    // the first segment is fetched here, to report errors and to know the codec
+    let first = fetcher
+        .next_segment()?
+        .ok_or_else(|| invalid("no segments"))?;
+    let kind = fetcher
+        .demuxer
+        .kind()
+        .or_else(|| AudioKind::sniff(&first))
+        .ok_or_else(|| invalid("no audio stream in the segments"))?;
+    fetcher.tap.set_extension(kind.extension());
+
+    let (sender, receiver) = mpsc::sync_channel(SEGMENTS_AHEAD);
+    thread::spawn(move || fetcher.run(sender));
+    Chunks::new(receiver, first).into_format(Some(kind.extension()))
+}
+
+/// Walks a media playlist, reloading it while it is live.
+struct Fetcher {
+    agent: ureq::Agent,
+    url: Url,
+    /// Media sequence number of the next segment not yet queued.
+    sequence: u64,
+    /// The segments to fetch, with the titles the playlist gives them.
+    pending: VecDeque<(Url, Option<String>)>,
+    ended: bool,
+    target_duration: Duration,
+    last_reload: Instant,
+    demuxer: TsDemuxer,
+    tap: Arc<StreamTap>,
+}
+
+impl Fetcher {
+    fn new(agent: ureq::Agent, url: Url, tap: Arc<StreamTap>) -> Result<Self, Error> {
+        let (url, playlist) = match fetch_playlist(&agent, &url)? {
+            Playlist::MediaPlaylist(playlist) => (url, playlist),
+            Playlist::MasterPlaylist(master) => {
+                let url = url.join(&pick_variant(&master)?).map_err(invalid)?;
+                match fetch_playlist(&agent, &url)? {
+                    Playlist::MediaPlaylist(playlist) => (url, playlist),
+                    Playlist::MasterPlaylist(_) => return Err(invalid("nested master playlist")),
+                }
+            }
+        };
+        let mut fetcher = Self {
+            agent,
+            url,
+            sequence: 0,
+            pending: VecDeque::new(),
+            ended: false,
+            target_duration: Duration::ZERO,
+            last_reload: Instant::now(),
+            demuxer: TsDemuxer::default(),
+            tap,
+        };
+        let first = playlist.media_sequence.max(0) as u64;
+        fetcher.sequence = if playlist.end_list {
+            first
+        } else {
+            first + playlist.segments.len().saturating_sub(LIVE_START) as u64
+        };
+        fetcher.update(playlist)?;
+        Ok(fetcher)
+    }
+
+    fn run(mut self, sender: SyncSender<io::Result<Vec<u8>>>) {
+        loop {
+            let segment = match self.next_segment() {
+                Ok(Some(segment)) => Ok(segment),
+                Ok(None) => return,
+                Err(err) => Err(io::Error::other(err.to_string())),
+            };
+            let failed = segment.is_err();
+            // a failed send means the stream was dropped
+            if sender.send(segment).is_err() || failed {
+                return;
+            }
+        }
+    }
+
+    /// The audio of the next segment, or `None` once an ended playlist is done.
+    fn next_segment(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        loop {
+            if let Some((url, title)) = self.pending.pop_front() {
+                let data = fetch(&self.agent, &url)?;
+                let audio = if TsDemuxer::detect(&data) {
+                    self.demuxer.demux(&data)
+                } else {
+                    strip_id3(&data).to_vec()
+                };
+                if let Some(title) = title.or_else(|| id3_title(&data)) {
+                    self.tap.set_title(Some(title));
+                }
+                self.tap.write(&audio);
+                return Ok(Some(audio));
+            }
+            if self.ended {
+                return Ok(None);
+            }
+            self.reload()?;
+        }
+    }
+
+    /// Fetches the playlist again, no sooner than half a target duration
+    /// after the last time, and queues the segments that are new.
+    fn reload(&mut self) -> Result<(), Error> {
+        let wait = (self.target_duration / 2).saturating_sub(self.last_reload.elapsed());
+        thread::sleep(wait);
+        match fetch_playlist(&self.agent, &self.url)? {
+            Playlist::MediaPlaylist(playlist) => self.update(playlist),
+            Playlist::MasterPlaylist(_) => Err(invalid("media playlist became a master playlist")),
+        }
+    }
+
+    fn update(&mut self, playlist: MediaPlaylist) -> Result<(), Error> {
+        self.last_reload = Instant::now();
+        self.target_duration = Duration::from_secs_f32(playlist.target_duration.max(1.0));
+        self.ended = playlist.end_list;
+        let first = playlist.media_sequence.max(0) as u64;
+        for (sequence, segment) in (first..).zip(playlist.segments) {
+            if sequence < self.sequence {
+                continue;
+            }
+            if segment.key.is_some_and(|key| key.method != "NONE") {
+                return Err(Error::Unsupported("encrypted HLS segments"));
+            }
+            if segment.map.is_some() {
+                return Err(Error::Unsupported("fragmented MP4 HLS segments"));
+            }
+            let title = segment
+                .title
+                .map(|title| title.trim().to_string())
+                .filter(|title| !title.is_empty());
+            self.pending
+                .push_back((self.url.join(&segment.uri).map_err(invalid)?, title));
+            self.sequence = sequence + 1;
+        }
+        Ok(())
+    }
+}
+
+/// The playlist of the best audio the master offers: an audio rendition if
+/// the variants have one, else the variant with the highest bandwidth.
+fn pick_variant(master: &MasterPlaylist) -> Result<String, Error> {
+    let variant = master
+        .variants
+        .iter()
+        .filter(|variant| !variant.is_i_frame)
+        .max_by_key(|variant| variant.bandwidth.parse::<u64>().unwrap_or(0))
+        .ok_or_else(|| invalid("master playlist without variants"))?;
+    let rendition = master.alternatives.iter().find(|media| {
+        media.media_type == AlternativeMediaType::Audio
+            && Some(&media.group_id) == variant.audio.as_ref()
+            && media.uri.is_some()
+    });
+    Ok(match rendition.and_then(|media| media.uri.clone()) {
+        Some(uri) => uri,
+        None => variant.uri.clone(),
+    })
+}
+
+fn fetch(agent: &ureq::Agent, url: &Url) -> Result<Vec<u8>, Error> {
+    let response = agent
+        .request_url("GET", url)
+        .call()
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    let mut data = vec![];
+    response.into_reader().read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn fetch_playlist(agent: &ureq::Agent, url: &Url) -> Result<Playlist, Error> {
+    let data = fetch(agent, url)?;
+    m3u8_rs::parse_playlist_res(&data).map_err(|_| invalid("not an HLS playlist"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::{
+        probe::tests::{bundled_mp3, decode_all},
+        stream::tests::Server,
+        ts::tests::{adts_silence, ts_segment},
+    };
+
+    fn syncsafe_bytes(size: usize) -> [u8; 4] {
+        [
+            (size >> 21) as u8 & 0x7f,
+            (size >> 14) as u8 & 0x7f,
+            (size >> 7) as u8 & 0x7f,
+            size as u8 & 0x7f,
+        ]
+    }
+
+    /// An ID3v2.4 tag holding UTF-8 text frames.
+    fn id3(frames: &[(&[u8; 4], &str)]) -> Vec<u8> {
+        let mut body = vec![];
+        for (id, text) in frames {
+            body.extend_from_slice(*id);
+            body.extend_from_slice(&syncsafe_bytes(1 + text.len()));
+            body.extend_from_slice(&[0, 0, 3]);
+            body.extend_from_slice(text.as_bytes());
+        }
+        let mut tag = b"ID3\x04\x00\x00".to_vec();
+        tag.extend_from_slice(&syncsafe_bytes(body.len()));
+        tag.extend(body);
+        tag
+    }
+
+    fn media_playlist(sequence: u64, segments: &[(&str, &str)], ended: bool) -> String {
+        let mut playlist = format!(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:1\n#EXT-X-MEDIA-SEQUENCE:{}\n",
+            sequence
+        );
+        for (uri, title) in segments {
+            playlist.push_str(&format!("#EXTINF:1.0,{}\n{}\n", title, uri));
+        }
+        if ended {
+            playlist.push_str("#EXT-X-ENDLIST\n");
+        }
+        playlist
+    }
+
+    #[test]
+    fn master_playlist_plays_best_variant_from_ts() {
+        let master = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=64000\nlow.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=128000\nhigh.m3u8\n";
+        let high = media_playlist(0, &[("a.ts", ""), ("b.ts", ""), ("c.ts", "")], true);
+        let segment = ts_segment(0x0f, &adts_silence(20));
+        let server = Server::start(&[
+            ("master.m3u8", master.into()),
+            ("high.m3u8", high.into()),
+            ("a.ts", segment.clone()),
+            ("b.ts", segment.clone()),
+            ("c.ts", segment),
+        ]);
+        let tap = Arc::new(StreamTap::new());
+
+        let format = open(&format!("{}master.m3u8", server.url), tap).unwrap();
+
+        assert_eq!(decode_all(format), 3 * 20 * 1024 * 2);
+        assert!(server.requested("low").is_empty());
+        assert_eq!(
+            server.requested(""),
+            ["master.m3u8", "high.m3u8", "a.ts", "b.ts", "c.ts"]
+        );
+    }
+
+    #[test]
+    fn audio_rendition_is_preferred_over_the_variant() {
+        let master = "#EXTM3U\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Main\",URI=\"audio.m3u8\"\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=128000,AUDIO=\"aac\"\nvideo.m3u8\n";
+        let audio = media_playlist(0, &[("a.ts", "")], true);
+        let server = Server::start(&[
+            ("master.m3u8", master.into()),
+            ("audio.m3u8", audio.into()),
+            ("a.ts", ts_segment(0x0f, &adts_silence(4))),
+        ]);
+
+        let format = open(
+            &format!("{}master.m3u8", server.url),
+            Arc::new(StreamTap::new()),
+        )
+        .unwrap();
+
+        assert_eq!(decode_all(format), 4 * 1024 * 2);
+        assert!(server.requested("video").is_empty());
+    }
+
+    #[test]
+    fn packed_mp3_segments_give_their_id3_titles() {
+        let mp3 = bundled_mp3();
+        let (first, second) = mp3[..400_000].split_at(200_000);
+        let mut a = id3(&[(b"TPE1", "Artist"), (b"TIT2", "First")]);
+        a.extend_from_slice(first);
+        let mut b = id3(&[(b"TIT2", "Second")]);
+        b.extend_from_slice(second);
+        let playlist = media_playlist(0, &[("a.mp3", ""), ("b.mp3", "")], true);
+        let first_only = media_playlist(0, &[("a.mp3", "")], true);
+        let server = Server::start(&[
+            ("radio.m3u8", playlist.into()),
+            ("first.m3u8", first_only.into()),
+            ("a.mp3", a),
+            ("b.mp3", b),
+        ]);
+        // segments are fetched ahead, so the first title is only certain alone
+        let tap = Arc::new(StreamTap::new());
+        decode_all(open(&format!("{}first.m3u8", server.url), tap.clone()).unwrap());
+        assert_eq!(tap.title().as_deref(), Some("Artist - First"));
+        let tap = Arc::new(StreamTap::new());
+
+        let format = open(&format!("{}radio.m3u8", server.url), tap.clone()).unwrap();
+        let samples = decode_all(format);
+
+        assert_eq!(tap.title().as_deref(), Some("Second"));
+        // the tags are stripped, so the audio decodes as the file's own bytes do
+        let direct = Chunks::new(mpsc::sync_channel(1).1, mp3[..400_000].to_vec())
+            .into_format(Some("mp3"))
+            .unwrap();
+        assert_eq!(samples, decode_all(direct));
+    }
+
+    #[test]
+    fn live_playlist_starts_near_the_edge_and_reloads() {
+        let segment = ts_segment(0x0f, &adts_silence(10));
+        let segments: Vec<(String, Vec<u8>)> = (10..17)
+            .map(|n| (format!("s{}.ts", n), segment.clone()))
+            .collect();
+        let mut files: Vec<(&str, Vec<u8>)> = segments
+            .iter()
+            .map(|(path, data)| (path.as_str(), data.clone()))
+            .collect();
+        let live = media_playlist(
+            10,
+            &[
+                ("s10.ts", ""),
+                ("s11.ts", ""),
+                ("s12.ts", ""),
+                ("s13.ts", ""),
+                ("s14.ts", ""),
+            ],
+            false,
+        );
+        files.push(("live.m3u8", live.into()));
+        let server = Server::start(&files);
+        let tap = Arc::new(StreamTap::new());
+
+        let format = open(&format!("{}live.m3u8", server.url), tap.clone()).unwrap();
+        // the next reload is half a target duration away, long enough to
+        // slide the window on by two segments, the last one ending it
+        server.set(
+            "live.m3u8",
+            media_playlist(
+                12,
+                &[
+                    ("s12.ts", ""),
+                    ("s13.ts", ""),
+                    ("s14.ts", ""),
+                    ("s15.ts", ""),
+                    ("s16.ts", "Next"),
+                ],
+                true,
+            ),
+        );
+        let samples = decode_all(format);
+
+        assert_eq!(
+            server.requested("s"),
+            ["s12.ts", "s13.ts", "s14.ts", "s15.ts", "s16.ts"]
+        );
+        assert_eq!(server.requested("live").len(), 2);
+        assert_eq!(samples, 5 * 10 * 1024 * 2);
+        assert_eq!(tap.title().as_deref(), Some("Next"));
+    }
+
+    #[test]
+    fn encrypted_segments_are_refused() {
+        let playlist = "#EXTM3U\n#EXT-X-TARGETDURATION:1\n\
+            #EXT-X-KEY:METHOD=AES-128,URI=\"key\"\n#EXTINF:1.0,\na.ts\n#EXT-X-ENDLIST\n";
+        let server = Server::start(&[("radio.m3u8", playlist.into())]);
+
+        let opened = open(
+            &format!("{}radio.m3u8", server.url),
+            Arc::new(StreamTap::new()),
+        );
+
+        assert!(matches!(opened, Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn id3_titles_read_each_text_encoding() {
+        assert_eq!(
+            id3_title(&id3(&[(b"TIT2", "Song")])).as_deref(),
+            Some("Song")
+        );
+        assert_eq!(
+            id3_title(&id3(&[(b"TIT2", "Song"), (b"TPE1", "Band")])).as_deref(),
+            Some("Band - Song")
+        );
+        // ID3v2.3, UTF-16 with a byte order mark, sizes as plain integers
+        let text: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("Ünïcode".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let mut tag = b"ID3\x03\x00\x00".to_vec();
+        tag.extend_from_slice(&syncsafe_bytes(10 + 1 + text.len()));
+        tag.extend_from_slice(b"TIT2");
+        tag.extend_from_slice(&(1 + text.len() as u32).to_be_bytes());
+        tag.extend_from_slice(&[0, 0, 1]);
+        tag.extend(text);
+        assert_eq!(id3_title(&tag).as_deref(), Some("Ünïcode"));
+        assert_eq!(id3_title(b"no tag here"), None);
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    io::{self, Read},
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc,
+    },
+    thread,
+};
+
+use symphonia::core::{errors::Error, formats::FormatReader};
+
+use super::stream::{agent, Chunks, StreamTap};
+
+/// Bytes read from the station at a time.
+const CHUNK: usize = 16 * 1024;
+
+/// How many chunks are buffered ahead of the decoder, some ten seconds of
+/// a 128 kbps station.
+const BUFFERED_CHUNKS: usize = 10;
+
+/// Opens the Icecast or Shoutcast stream at `url`, or any audio served
+/// over plain HTTP, as a demuxer over the audio as it arrives. The stream
+/// is read on a thread until the demuxer is dropped, passing the titles in
+/// its ICY metadata and the audio it reads to `tap`. This waits on the
+/// network for the first chunk, so the player calls it off the engine thread.
+pub fn open(url: &str, tap: Arc<StreamTap>) -> Result<Box<dyn FormatReader>, Error> {
+    let response = agent()
+        .get(url)
+        .set("Icy-MetaData", "1")
+        .call()
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    let extension = extension(response.content_type());
+    tap.set_extension(extension.unwrap_or("bin"));
+    let metaint = response
+        .header("icy-metaint")
+        .and_then(|metaint| metaint.trim().parse().ok())
+        .filter(|metaint| *metaint > 0);
+    let reader = IcyReader {
+        inner: response.into_reader(),
+        metaint,
+        until_meta: metaint.unwrap_or(0),
+        tap: tap.clone(),
+    };
+
+    let (sender, receiver) = mpsc::sync_channel(BUFFERED_CHUNKS);
+    thread::spawn(move || pump(reader, &tap, sender));
+    Chunks::new(receiver, vec![]).into_format(extension)
+}
+
+fn pump<R: Read>(mut reader: R, tap: &StreamTap, sender: SyncSender<io::Result<Vec<u8>>>) {
+    loop {
+        let mut chunk = vec![0; CHUNK];
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => return,
+            Ok(n) => {
+                chunk.truncate(n);
+                // a read stops at the metadata, so the title is this chunk's own
+                tap.write(&chunk);
+                Ok(chunk)
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => Err(err),
+        };
+        let failed = read.is_err();
+        // a failed send means the stream was dropped
+        if sender.send(read).is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// The extension symphonia's probe picks a reader by for a content type.
+fn extension(content_type: &str) -> Option<&'static str> {
+    match content_type {
+        "audio/mpeg" | "audio/mp3" => Some("mp3"),
+        "audio/aac" | "audio/aacp" | "audio/x-aac" => Some("aac"),
+        "audio/ogg" | "application/ogg" => Some("ogg"),
+        "audio/flac" => Some("flac"),
+        _ => None,
+    }
+}
+
+/// Passes the audio through, taking out the metadata block the server puts
+/// after every `metaint` bytes of it.
+struct IcyReader<R> {
+    inner: R,
+    metaint: Option<usize>,
+    until_meta: usize,
+    tap: Arc<StreamTap>,
+}
+
+impl<R: Read> IcyReader<R> {
+    fn read_meta(&mut self) -> io::Result<()> {
+        let mut len = [0];
+        self.inner.read_exact(&mut len)?;
+        // the length is counted in blocks of 16 bytes
+        let mut meta = vec![0; len[0] as usize * 16];
+        self.inner.read_exact(&mut meta)?;
+        // an empty block means the title hasn't changed
+        if !meta.is_empty() {
+            self.tap
+                .set_title(stream_title(&String::from_utf8_lossy(&meta)));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for IcyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let metaint = match self.metaint {
+            Some(metaint) => metaint,
+            None => return self.inner.read(buf),
+        };
+        if self.until_meta == 0 {
+            self.read_meta()?;
+            self.until_meta = metaint;
+        }
+        let len = buf.len().min(self.until_meta);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.until_meta -= n;
+        Ok(n)
+    }
+}
+
+/// The title in metadata such as `StreamTitle='Artist - Song';StreamUrl='';`.
+fn stream_title(meta: &str) -> Option<String> {
+    let start = meta.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &meta[start..];
+    // the title may hold quotes itself, so it runs to the quote before `;`
+    let end = rest.find("';").unwrap_or(rest.trim_end_matches('\0').len());
+    let title = rest[..end].trim_end_matches('\'').trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::media::{
+        probe::tests::{bundled_mp3, decode_all},
+        stream::tests::Server,
+        ts::tests::adts_silence,
+    };
+
+    const METAINT: usize = 64;
+
+    /// A metadata block as a server sends it, padded to 16 bytes.
+    fn meta_block(meta: &str) -> Vec<u8> {
+        let blocks = meta.len().div_ceil(16);
+        let mut block = vec![blocks as u8];
+        block.extend_from_slice(meta.as_bytes());
+        block.resize(1 + blocks * 16, 0);
+        block
+    }
+
+    /// `audio` with the blocks of `metas` after every `METAINT` bytes of it,
+    /// an empty block wherever they run out.
+    fn icy_stream(audio: &[u8], metas: &[&str]) -> Vec<u8> {
+        let mut stream = vec![];
+        for (i, chunk) in audio.chunks(METAINT).enumerate() {
+            stream.extend_from_slice(chunk);
+            if chunk.len() == METAINT {
+                stream.extend(meta_block(metas.get(i).copied().unwrap_or("")));
+            }
+        }
+        stream
+    }
+
+    fn audio(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Reads at most `step` bytes at a time, as a network stream may.
+    struct Trickle<R> {
+        inner: R,
+        step: usize,
+    }
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.step);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    fn icy_reader<R: Read>(inner: R, tap: &Arc<StreamTap>) -> IcyReader<R> {
+        IcyReader {
+            inner,
+            metaint: Some(METAINT),
+            until_meta: METAINT,
+            tap: tap.clone(),
+        }
+    }
+
+    #[test]
+    fn metadata_is_taken_out_of_the_audio() {
+        let audio = audio(10 * METAINT + 10);
+        let stream = icy_stream(
+            &audio,
+            &[
+                "StreamTitle='First';",
+                "",
+                "StreamTitle='Second';StreamUrl='';",
+            ],
+        );
+        for step in [1, 7, METAINT, 1000] {
+            let tap = Arc::new(StreamTap::new());
+            let mut reader = icy_reader(
+                Trickle {
+                    inner: stream.as_slice(),
+                    step,
+                },
+                &tap,
+            );
+            let mut read = vec![];
+            reader.read_to_end(&mut read).unwrap();
+            assert_eq!(read, audio, "reading {} bytes at a time", step);
+            assert_eq!(tap.title().as_deref(), Some("Second"));
+        }
+    }
+
+    #[test]
+    fn titles_change_at_their_blocks() {
+        let audio = audio(4 * METAINT);
+        let stream = icy_stream(
+            &audio,
+            &["StreamTitle='First';", "", "StreamTitle='Second';"],
+        );
+        let tap = Arc::new(StreamTap::new());
+        let mut reader = icy_reader(stream.as_slice(), &tap);
+        let mut block = vec![0; METAINT];
+        let mut titles = vec![];
+        for _ in 0..4 {
+            reader.read_exact(&mut block).unwrap();
+            titles.push(tap.title());
+        }
+        // an empty block keeps the title it follows
+        let first = Some("First".to_string());
+        assert_eq!(
+            titles,
+            [None, first.clone(), first, Some("Second".to_string())]
+        );
+    }
+
+    #[test]
+    fn empty_title_clears_it() {
+        let audio = audio(3 * METAINT);
+        let stream = icy_stream(&audio, &["StreamTitle='First';", "StreamTitle='';"]);
+        let tap = Arc::new(StreamTap::new());
+        let mut read = vec![];
+        icy_reader(stream.as_slice(), &tap)
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, audio);
+        assert_eq!(tap.title(), None);
+    }
+
+    #[test]
+    fn stream_without_metaint_passes_through() {
+        let audio = audio(1000);
+        let tap = Arc::new(StreamTap::new());
+        let mut reader = IcyReader {
+            inner: audio.as_slice(),
+            metaint: None,
+            until_meta: 0,
+            tap: tap.clone(),
+        };
+        let mut read = vec![];
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, audio);
+        assert_eq!(tap.title(), None);
+    }
+
+    #[test]
+    fn cut_off_metadata_is_an_error() {
+        let mut stream = audio(METAINT);
+        stream.extend_from_slice(&[2, b'S', b't']);
+        let tap = Arc::new(StreamTap::new());
+        let mut read = vec![];
+        let err = icy_reader(stream.as_slice(), &tap)
+            .read_to_end(&mut read)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn pump_sends_the_clean_audio() {
+        let audio = audio(CHUNK + 3 * METAINT);
+        let stream = icy_stream(&audio, &["StreamTitle='Song';"]);
+        let tap = Arc::new(StreamTap::new());
+        let (sender, receiver) = mpsc::sync_channel(BUFFERED_CHUNKS);
+        let pumped = tap.clone();
+        // a read stops at each metadata block, more chunks than the channel holds
+        thread::spawn(move || pump(icy_reader(stream.as_slice(), &pumped), &pumped, sender));
+        let sent: Vec<u8> = receiver.iter().flat_map(Result::unwrap).collect();
+        assert_eq!(sent, audio);
+        assert_eq!(tap.title().as_deref(), Some("Song"));
+    }
+
+    #[test]
+    fn stream_titles() {
+        assert_eq!(
+            stream_title("StreamTitle='Artist - Song';StreamUrl='';").as_deref(),
+            Some("Artist - Song")
+        );
+        assert_eq!(
+            stream_title("StreamTitle='Guns N' Roses - Don't Cry';StreamUrl='http://x';")
+                .as_deref(),
+            Some("Guns N' Roses - Don't Cry")
+        );
+        assert_eq!(
+            stream_title("StreamTitle='Rock 'n' Roll'\0\0\0").as_deref(),
+            Some("Rock 'n' Roll")
+        );
+        assert_eq!(
+            stream_title("StreamTitle='Ends in a quote'';").as_deref(),
+            Some("Ends in a quote")
+        );
+        assert_eq!(stream_title("StreamTitle='  ';"), None);
+        assert_eq!(stream_title("StreamUrl='http://x';"), None);
+        assert_eq!(stream_title(""), None);
+    }
+
+    /// The extension `tap` records the stream with.
+    fn recorded_extension(tap: &StreamTap) -> String {
+        let path = tap.start_recording(&env::temp_dir(), "icy test").unwrap();
+        tap.stop_recording();
+        fs::remove_file(&path).unwrap();
+        path.extension().unwrap().to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn open_asks_for_metadata_and_takes_it_out() {
+        let mp3 = bundled_mp3()[..200_000].to_vec();
+        let stream = icy_stream(&mp3, &["StreamTitle='Band - It's a Song';StreamUrl='';"]);
+        let metaint = METAINT.to_string();
+        let server = Server::start(&[]);
+        server.set_with_headers(
+            "radio",
+            &[("Content-Type", "audio/mpeg"), ("icy-metaint", &metaint)],
+            stream,
+        );
+        let tap = Arc::new(StreamTap::new());
+
+        let format = open(&format!("{}radio", server.url), tap.clone()).unwrap();
+        let samples = decode_all(format);
+
+        assert_eq!(
+            server.request_header("radio", "Icy-MetaData").as_deref(),
+            Some("1")
+        );
+        // left in, the metadata would corrupt frames and change what decodes
+        let direct = Chunks::new(mpsc::sync_channel(1).1, mp3)
+            .into_format(Some("mp3"))
+            .unwrap();
+        assert_eq!(samples, decode_all(direct));
+        assert_eq!(tap.title().as_deref(), Some("Band - It's a Song"));
+        assert_eq!(recorded_extension(&tap), "mp3");
+    }
+
+    #[test]
+    fn open_plays_plain_http_without_metadata() {
+        let server = Server::start(&[]);
+        server.set_with_headers("aac", &[("Content-Type", "audio/aacp")], adts_silence(50));
+        let tap = Arc::new(StreamTap::new());
+
+        let format = open(&format!("{}aac", server.url), tap.clone()).unwrap();
+
+        assert_eq!(decode_all(format), 50 * 1024 * 2);
+        assert_eq!(tap.title(), None);
+        assert_eq!(recorded_extension(&tap), "aac");
+    }
+
+    #[test]
+    fn unknown_content_type_is_probed() {
+        let mp3 = bundled_mp3()[..50_000].to_vec();
+        let server = Server::start(&[]);
+        server.set_with_headers(
+            "radio",
+            &[("Content-Type", "application/octet-stream")],
+            mp3,
+        );
+        let tap = Arc::new(StreamTap::new());
+
+        let format = open(&format!("{}radio", server.url), tap.clone()).unwrap();
+
+        assert!(decode_all(format) > 0);
+        assert_eq!(recorded_extension(&tap), "bin");
+    }
+
+    #[test]
+    fn content_types_map_to_extensions() {
+        assert_eq!(extension("audio/mpeg"), Some("mp3"));
+        assert_eq!(extension("audio/aacp"), Some("aac"));
+        assert_eq!(extension("application/ogg"), Some("ogg"));
+        assert_eq!(extension("audio/flac"), Some("flac"));
+        assert_eq!(extension("text/html"), None);
+    }
+}
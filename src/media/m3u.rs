@@ -0,0 +1,101 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{self, Path, PathBuf},
+};
+
+use url::Url;
+
+use super::{
+    media::{Media, Source},
+    player::PlayListItem,
+};
+use crate::ui::radio::RadioConfig;
+
+/// A line of a playlist, with the title its `#EXTINF` gave it.
+pub struct Entry {
+    pub media: Media,
+    pub title: Option<String>,
+}
+
+/// Reads an M3U playlist, extended or not. Relative paths are taken from
+/// the playlist's folder, and urls become radio streams.
+pub fn read(path: &Path) -> io::Result<Vec<Entry>> {
+    let data = fs::read(path)?;
+    let text = String::from_utf8_lossy(&data);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut entries = vec![];
+    let mut title = None;
+    for line in text.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            title = extinf_title(extinf);
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let title = title.take();
+        let src = match Url::parse(line) {
+            Ok(url) if url.scheme() == "file" => match url.to_file_path() {
+                Ok(file) => Source::Local(file.to_string_lossy().to_string()),
+                Err(_) => continue,
+            },
+            // a drive letter parses as a one-letter scheme
+            Ok(url) if url.scheme().len() > 1 => {
+                let radio = RadioConfig {
+                    name: title.clone().unwrap_or_else(|| line.to_string()),
+                    url: line.to_string(),
+                    genres: vec![],
+                    favourite: false,
+                    comments: vec![],
+                };
+                radio.media().src
+            }
+            _ => Source::Local(dir.join(line).to_string_lossy().to_string()),
+        };
+        entries.push(Entry {
+            media: Media { src },
+            title,
+        });
+    }
+    Ok(entries)
+}
+
+/// The title after the length in `#EXTINF:<length>,<title>`. The length
+/// isn't needed, files are probed for theirs when added.
+fn extinf_title(extinf: &str) -> Option<String> {
+    let title = extinf.split_once(',')?.1.trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// Writes `items` as an extended M3U playlist. Files under the playlist's
+/// folder are written relative to it, so the folder can be moved whole.
+pub fn write(path: &Path, items: &[PlayListItem]) -> io::Result<()> {
+    let path = path::absolute(path)?;
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut f = BufWriter::new(File::create(&path)?);
+    writeln!(f, "#EXTM3U")?;
+    for item in items {
+        let (length, location) = match item.stream {
+            Some(_) => (-1, item.path.clone()),
+            None => (
+                item.duration.as_secs() as i64,
+                location(&dir, &item.path)?.to_string_lossy().to_string(),
+            ),
+        };
+        // a line break would end the title early
+        let title = item.name.replace(['\r', '\n'], " ");
+        writeln!(f, "#EXTINF:{},{}", length, title)?;
+        writeln!(f, "{}", location)?;
+    }
+    f.flush()
+}
+
+fn location(dir: &Path, file: &str) -> io::Result<PathBuf> {
+    let file = path::absolute(file)?;
+    Ok(match file.strip_prefix(dir) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => file,
+    })
+}
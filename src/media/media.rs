@@ -1,5 +1,10 @@
+use crate::ui::radio::RadioConfig;
+
 pub enum Source {
-    // M3u8(RadioConfig),
+    /// An HLS stream, from a master or a media playlist.
+    M3u8(RadioConfig),
+    /// Icecast, Shoutcast or other audio served over plain HTTP.
+    Http(RadioConfig),
     Local(String),
 }
 
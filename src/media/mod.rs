@@ -1,2 +1,22 @@
+pub mod device;
+pub mod engine;
+pub mod equalizer;
+pub mod error;
+pub mod hls;
+pub mod icy;
+pub mod m3u;
+pub mod media;
+#[cfg(feature = "opus")]
+pub mod opus;
+pub mod output;
 pub mod player;
-pub mod media;
\ No newline at end of file
+pub mod probe;
+pub mod record;
+pub mod replay_gain;
+pub mod shuffle;
+pub mod spectrum;
+pub mod stream;
+pub mod stretch;
+pub mod timeshift;
+pub mod track;
+pub mod ts;
@@ -0,0 +1,113 @@
+use std::sync::Mutex;
+
+use audiopus::{
+    coder::{Decoder as Libopus, GenericCtl},
+    packet::Packet as OpusPacket,
+    Channels, MutSignals, SampleRate,
+};
+use symphonia::core::{
+    audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Signal, SignalSpec},
+    codecs::{
+        CodecDescriptor, CodecParameters, Decoder, DecoderOptions, FinalizeResult, CODEC_TYPE_OPUS,
+    },
+    errors::{decode_error, unsupported_error, Result},
+    formats::Packet,
+    support_codec,
+};
+
+/// libopus always decodes to 48 kHz here, whatever rate the stream was made at.
+const SAMPLE_RATE: u32 = 48000;
+
+/// The longest an Opus packet can be, 120 ms.
+const MAX_FRAMES: usize = 5760;
+
+/// Decodes the Opus tracks symphonia demuxes from Ogg, which it has no
+/// decoder of its own for. Only mono and stereo streams are handled.
+pub struct OpusDecoder {
+    params: CodecParameters,
+    // libopus' decoder may move between threads but not be shared
+    decoder: Mutex<Libopus>,
+    interleaved: Vec<f32>,
+    buf: AudioBuffer<f32>,
+}
+
+impl Decoder for OpusDecoder {
+    fn try_new(params: &CodecParameters, _options: &DecoderOptions) -> Result<Self> {
+        let layout = match params.channels {
+            Some(layout) => layout,
+            None => return unsupported_error("opus: unknown channel count"),
+        };
+        let channels = match layout.count() {
+            1 => Channels::Mono,
+            2 => Channels::Stereo,
+            _ => return unsupported_error("opus: more than two channels"),
+        };
+        let decoder = match Libopus::new(SampleRate::Hz48000, channels) {
+            Ok(decoder) => decoder,
+            Err(_) => return unsupported_error("opus: libopus refused the stream"),
+        };
+        Ok(Self {
+            params: params.clone(),
+            decoder: Mutex::new(decoder),
+            interleaved: vec![0.0; MAX_FRAMES * layout.count()],
+            buf: AudioBuffer::new(MAX_FRAMES as u64, SignalSpec::new(SAMPLE_RATE, layout)),
+        })
+    }
+
+    fn supported_codecs() -> &'static [CodecDescriptor] {
+        &[support_codec!(CODEC_TYPE_OPUS, "opus", "Opus (libopus)")]
+    }
+
+    fn reset(&mut self) {
+        let _ = self.decoder.lock().unwrap().reset_state();
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        let input = match OpusPacket::try_from(packet.buf()) {
+            Ok(input) => input,
+            Err(_) => return decode_error("opus: empty packet"),
+        };
+        let output = match MutSignals::try_from(&mut self.interleaved[..]) {
+            Ok(output) => output,
+            Err(_) => return decode_error("opus: no room to decode into"),
+        };
+        let frames = match self
+            .decoder
+            .lock()
+            .unwrap()
+            .decode_float(Some(input), output, false)
+        {
+            Ok(frames) => frames,
+            Err(_) => return decode_error("opus: invalid packet"),
+        };
+
+        self.buf.clear();
+        self.buf.render_reserved(Some(frames));
+        let channels = self.buf.spec().channels.count();
+        for channel in 0..channels {
+            let samples = self
+                .interleaved
+                .chunks(channels)
+                .map(|frame| frame[channel]);
+            for (dst, sample) in self.buf.chan_mut(channel).iter_mut().zip(samples) {
+                *dst = sample;
+            }
+        }
+        // drops the pre-skip at the start and the padding at the end
+        self.buf
+            .trim(packet.trim_start() as usize, packet.trim_end() as usize);
+        Ok(self.buf.as_audio_buffer_ref())
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        FinalizeResult::default()
+    }
+
+    fn last_decoded(&self) -> AudioBufferRef<'_> {
+        self.buf.as_audio_buffer_ref()
+    }
+}
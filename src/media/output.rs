@@ -0,0 +1,238 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use failure::Error;
+use hound::{SampleFormat as WavFormat, WavSpec, WavWriter};
+use rodio::{
+    cpal::{
+        self,
+        traits::{HostTrait, StreamTrait},
+        Sample, SampleFormat, StreamConfig,
+    },
+    dynamic_mixer::{self, DynamicMixerController},
+    DeviceTrait, Sink, StreamError,
+};
+
+use super::device::find_device;
+
+/// Channels and sample rate of the outputs that aren't a sound card.
+const SOFT_CHANNELS: u16 = 2;
+const SOFT_SAMPLE_RATE: u32 = 44100;
+
+/// Where the mixed audio goes.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum OutputBackend {
+    /// A sound card, through cpal.
+    Cpal,
+    /// Nowhere. Samples are still pulled in real time, so playback behaves
+    /// the same without audio hardware.
+    Null,
+    /// A 16-bit WAV file, written in real time.
+    Wav(PathBuf),
+}
+
+impl OutputBackend {
+    /// Parses `cpal`, `null` or `wav:<path>`, as given to `--output` or in `config.ini`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.split_once(':') {
+            Some(("wav", path)) if !path.is_empty() => {
+                Some(OutputBackend::Wav(PathBuf::from(path)))
+            }
+            None if s == "wav" => Some(OutputBackend::Wav(PathBuf::from("wy-media.wav"))),
+            None if s == "cpal" => Some(OutputBackend::Cpal),
+            None if s == "null" => Some(OutputBackend::Null),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for OutputBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputBackend::Cpal => write!(f, "cpal"),
+            OutputBackend::Null => write!(f, "null"),
+            OutputBackend::Wav(path) => write!(f, "wav:{}", path.display()),
+        }
+    }
+}
+
+enum Driver {
+    Stream {
+        _stream: cpal::Stream,
+    },
+    /// A thread pulling samples at the sample rate, stopped on drop.
+    Clock {
+        running: Arc<AtomicBool>,
+        handle: Option<JoinHandle<()>>,
+    },
+}
+
+/// An open output. Unlike rodio's `OutputStream` it notices when the device
+/// reports an error, which is how a device going away shows up.
+pub struct Output {
+    mixer: Arc<DynamicMixerController<f32>>,
+    lost: Arc<AtomicBool>,
+    driver: Driver,
+}
+
+impl Output {
+    /// Opens `backend`. For cpal that is the device with `id`, or the system
+    /// default when `None`.
+    pub fn open(backend: &OutputBackend, id: Option<&str>) -> Result<Self, Error> {
+        match backend {
+            OutputBackend::Cpal => Ok(Self::open_device(id)?),
+            OutputBackend::Null => Ok(Self::clock(|_| true)),
+            OutputBackend::Wav(path) => {
+                let spec = WavSpec {
+                    channels: SOFT_CHANNELS,
+                    sample_rate: SOFT_SAMPLE_RATE,
+                    bits_per_sample: 16,
+                    sample_format: WavFormat::Int,
+                };
+                let mut writer = Some(WavWriter::create(path, spec)?);
+                Ok(Self::clock(move |sample| write_wav(&mut writer, sample)))
+            }
+        }
+    }
+
+    fn open_device(id: Option<&str>) -> Result<Self, StreamError> {
+        let device = match id {
+            Some(id) => find_device(id),
+            None => cpal::default_host().default_output_device(),
+        }
+        .ok_or(StreamError::NoDevice)?;
+
+        let default = device.default_output_config()?;
+        match Self::build(&device, default) {
+            Ok(output) => Ok(output),
+            Err(err) => device
+                .supported_output_configs()?
+                .find_map(|config| Self::build(&device, config.with_max_sample_rate()).ok())
+                .ok_or(StreamError::BuildStreamError(err)),
+        }
+    }
+
+    fn build(
+        device: &cpal::Device,
+        format: cpal::SupportedStreamConfig,
+    ) -> Result<Self, cpal::BuildStreamError> {
+        let (mixer, mut samples) =
+            dynamic_mixer::mixer::<f32>(format.channels(), format.sample_rate().0);
+        let lost = Arc::new(AtomicBool::new(false));
+        let flag = lost.clone();
+        let on_error = move |_| flag.store(true, Ordering::Relaxed);
+        let config: StreamConfig = format.config();
+        let stream = match format.sample_format() {
+            SampleFormat::F32 => device.build_output_stream::<f32, _, _>(
+                &config,
+                move |data, _| {
+                    data.iter_mut()
+                        .for_each(|d| *d = samples.next().unwrap_or(0.0))
+                },
+                on_error,
+            ),
+            SampleFormat::I16 => device.build_output_stream::<i16, _, _>(
+                &config,
+                move |data, _| {
+                    data.iter_mut()
+                        .for_each(|d| *d = samples.next().map_or(0, |s| s.to_i16()))
+                },
+                on_error,
+            ),
+            SampleFormat::U16 => device.build_output_stream::<u16, _, _>(
+                &config,
+                move |data, _| {
+                    data.iter_mut()
+                        .for_each(|d| *d = samples.next().map_or(u16::MAX / 2, |s| s.to_u16()))
+                },
+                on_error,
+            ),
+        }?;
+        stream
+            .play()
+            .map_err(|_| cpal::BuildStreamError::DeviceNotAvailable)?;
+        Ok(Self {
+            mixer,
+            lost,
+            driver: Driver::Stream { _stream: stream },
+        })
+    }
+
+    /// Pulls samples on a thread at the rate a sound card would, handing each
+    /// one to `write` until it returns false. `write` gets `None` on close.
+    fn clock<F>(mut write: F) -> Self
+    where
+        F: FnMut(Option<f32>) -> bool + Send + 'static,
+    {
+        let (mixer, mut samples) = dynamic_mixer::mixer::<f32>(SOFT_CHANNELS, SOFT_SAMPLE_RATE);
+        let lost = Arc::new(AtomicBool::new(false));
+        let running = Arc::new(AtomicBool::new(true));
+        let (flag, keep_running) = (lost.clone(), running.clone());
+        let handle = thread::spawn(move || {
+            let start = Instant::now();
+            let mut pulled = 0u64;
+            while keep_running.load(Ordering::Relaxed) {
+                let due = (start.elapsed().as_secs_f64() * SOFT_SAMPLE_RATE as f64) as u64
+                    * SOFT_CHANNELS as u64;
+                while pulled < due {
+                    if !write(Some(samples.next().unwrap_or(0.0))) {
+                        flag.store(true, Ordering::Relaxed);
+                        return;
+                    }
+                    pulled += 1;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            write(None);
+        });
+        Self {
+            mixer,
+            lost,
+            driver: Driver::Clock {
+                running,
+                handle: Some(handle),
+            },
+        }
+    }
+
+    /// A sink that plays on this output.
+    pub fn new_sink(&self) -> Sink {
+        let (sink, queue) = Sink::new_idle();
+        self.mixer.add(queue);
+        sink
+    }
+
+    /// Whether the device has failed since the stream was opened.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        if let Driver::Clock { running, handle } = &mut self.driver {
+            running.store(false, Ordering::Relaxed);
+            if let Some(handle) = handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+/// Writes a sample, or finishes the file on `None` so its header is complete.
+fn write_wav(writer: &mut Option<WavWriter<BufWriter<File>>>, sample: Option<f32>) -> bool {
+    match (sample, writer.as_mut()) {
+        (Some(sample), Some(w)) => w.write_sample(sample.to_i16()).is_ok(),
+        (Some(_), None) => false,
+        (None, _) => writer.take().is_none_or(|w| w.finalize().is_ok()),
+    }
+}
@@ -1,52 +1,143 @@
 use std::{
-    fs::File,
-    io::BufReader,
     ops::Add,
-    path::Path,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
     time::{Duration, Instant},
 };
 
-use rodio::{cpal, Decoder, OutputStream, OutputStreamHandle, Sink};
-use tui::widgets::ListState;
+use rodio::{Sink, Source};
+use symphonia::core::errors::Error;
 
-use super::media::{self, Media};
+use crate::ui::radio::RadioConfig;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+use super::{
+    equalizer::{EqSettings, Equalizer, BANDS},
+    error::PlayerError,
+    hls, icy, m3u,
+    media::{self, Media},
+    output::{Output, OutputBackend},
+    probe::probe,
+    replay_gain::{LoudnessCache, ReplayGain, ReplayGainMode},
+    shuffle::ShuffleOrder,
+    spectrum::{Spectrum, SpectrumTap},
+    stream::StreamTap,
+    stretch::{PlaybackRate, TimeStretch},
+    timeshift::TimeShift,
+    track::{Played, Track, TrackControl},
+};
+
+/// How far into a track `previous` restarts it instead of going back.
+const RESTART_THRESHOLD: Duration = Duration::from_secs(3);
+
+/// How often to look for an output device while there is none.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long `seek` waits for the track to take a seek before leaving it to
+/// `tick` to pick up the new position.
+const SEEK_WAIT: Duration = Duration::from_millis(500);
+
+pub const MIN_RATE: f32 = 0.5;
+pub const MAX_RATE: f32 = 3.0;
+
+/// The source chain the sink plays for each track.
+type TrackSource = Played<SpectrumTap<Equalizer<TimeStretch<Track>>>>;
+
+/// A station's track, or why it couldn't be opened.
+type Opened = Result<(Track, Arc<TrackControl>), Error>;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum PlayStatus {
     Waiting,
-    Playing(Instant, Duration),
-    Stopped(Duration),
+    Playing,
+    Stopped,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RepeatMode {
+    Off,
+    One,
+    All,
 }
 
+impl RepeatMode {
+    pub fn cycle(self) -> Self {
+        match self {
+            RepeatMode::Off => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+        }
+    }
+}
+
+/// How a radio station streams its audio.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StreamKind {
+    /// HLS, segments listed in an m3u8 playlist.
+    Hls,
+    /// A single progressive HTTP response, as Icecast and Shoutcast serve.
+    Http,
+}
+
+#[derive(Clone)]
 pub struct PlayListItem {
     pub name: String,
     pub duration: Duration,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bitrate: u32,
+    pub replay_gain: ReplayGain,
     pub current_pos: Duration,
     pub status: PlayStatus,
     pub path: String,
+    /// Set when the track failed to open, so play order passes over it.
+    pub unplayable: bool,
+    /// Set for a live stream, which has no length and can't seek. `path` is its url.
+    pub stream: Option<StreamKind>,
+    /// The song a stream says it is playing.
+    pub title: Option<String>,
 }
 
 pub struct PlayList {
     pub lists: Vec<PlayListItem>,
-    pub index: ListState,
 }
 
+/// Moving through the playlist skips tracks that fail to open. Their errors
+/// are still returned once something plays, so they can be reported.
 pub trait Player {
-    fn new() -> Self;
-    fn add_to_list(&mut self, media: Media, once: bool) -> bool;
-    fn play(&mut self, song_index: usize) -> bool;
-    fn play_selected(&mut self, selected: usize) -> bool;
-    fn next(&mut self) -> bool;
-    fn stop(&mut self) -> bool;
-    fn pause(&mut self) -> bool;
-    fn resume(&mut self) -> bool;
+    fn new(backend: OutputBackend) -> Self;
+    fn add_to_list(&mut self, media: Media, once: bool) -> Result<(), PlayerError>;
+    fn play(&mut self, song_index: usize);
+    fn play_selected(&mut self, selected: usize) -> Result<(), PlayerError>;
+    fn next(&mut self) -> Result<(), PlayerError>;
+    fn previous(&mut self) -> Result<(), PlayerError>;
+    fn seek(&mut self, pos: Duration) -> Result<(), PlayerError>;
+    fn seek_by(&mut self, secs: i64) -> Result<(), PlayerError>;
+    fn stop(&mut self);
+    fn pause(&mut self);
+    fn resume(&mut self) -> Result<(), PlayerError>;
     fn get_progress(&self) -> (f32, f32);
     fn is_playing(&self) -> bool;
-    fn tick(&mut self);
+    fn tick(&mut self) -> Result<(), PlayerError>;
     fn volume(&self) -> f32;
-    fn set_volume(&mut self, new_volume: f32) -> bool;
-    fn load_new_song(&mut self, index: usize) -> bool;
-    fn remove_from_playlist(&mut self, song_index: usize) -> bool;
+    fn set_volume(&mut self, new_volume: f32);
+    fn set_crossfade(&mut self, crossfade: Duration);
+    fn rate(&self) -> f32;
+    fn set_rate(&mut self, rate: f32);
+    fn set_replay_gain(&mut self, mode: ReplayGainMode);
+    fn equalizer(&self) -> [f32; BANDS];
+    fn set_equalizer(&mut self, gains: [f32; BANDS]);
+    fn is_shuffle(&self) -> bool;
+    fn set_shuffle(&mut self, shuffle: bool);
+    fn repeat(&self) -> RepeatMode;
+    fn set_repeat(&mut self, repeat: RepeatMode);
+    fn output_device(&self) -> Option<&str>;
+    fn set_output_device(&mut self, device: Option<String>) -> Result<(), PlayerError>;
+    fn load_new_song(&mut self, index: usize) -> Result<(), PlayerError>;
+    fn remove_from_playlist(&mut self, song_index: usize) -> Result<(), PlayerError>;
 }
 
 pub struct MusicPlayer {
@@ -54,275 +145,1248 @@ pub struct MusicPlayer {
     pub total_time: Duration,
     pub play_list: PlayList,
     selected_song_index: usize,
-    stream: OutputStream,
-    stream_handle: OutputStreamHandle,
+    output: Option<Output>,
+    backend: OutputBackend,
+    device: Option<String>,
+    last_reconnect: Instant,
     sink: Sink,
+    fading: Option<Sink>,
+    crossfade: Duration,
+    rate: Arc<PlaybackRate>,
+    equalizer: Arc<EqSettings>,
+    spectrum: Arc<Spectrum>,
+    replay_gain: ReplayGainMode,
+    loudness: Arc<LoudnessCache>,
+    track: Option<Arc<TrackControl>>,
+    queued: Option<(usize, Arc<TrackControl>)>,
+    /// The tap on the last stream opened. Streams only open to play at
+    /// once, so while one is selected this is its own.
+    stream: Option<Arc<StreamTap>>,
+    /// The audio kept of the last stream opened, to pause and rewind it.
+    time_shift: Option<Arc<TimeShift>>,
+    /// The selected station while it is being connected to, see `connect`.
+    opening: Option<Receiver<Opened>>,
+    shuffle: Option<ShuffleOrder>,
+    repeat: RepeatMode,
     initialized: bool,
 }
 
 impl PlayList {
     pub fn default() -> Self {
-        let mut list_state = ListState::default();
-        list_state.select(Some(0));
-        Self {
-            lists: vec![],
-            index: list_state,
-        }
+        Self { lists: vec![] }
     }
 }
 
 impl Player for MusicPlayer {
-    fn new() -> Self {
-        for dev in cpal::available_hosts() {
-            println!("{:?}", dev);
-        }
-        let (stream, stream_handle) = OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&stream_handle).unwrap();
+    fn new(backend: OutputBackend) -> Self {
+        let output = Output::open(&backend, None).ok();
+        let sink = sink_on(output.as_ref());
         Self {
             current_time: Duration::from_secs(0),
             total_time: Duration::from_secs(0),
             play_list: PlayList::default(),
             selected_song_index: 0,
-            stream,
-            stream_handle,
+            output,
+            backend,
+            device: None,
+            last_reconnect: Instant::now(),
             sink,
+            fading: None,
+            crossfade: Duration::from_secs(0),
+            rate: Arc::new(PlaybackRate::new(1.0)),
+            equalizer: Arc::new(EqSettings::default()),
+            spectrum: Arc::new(Spectrum::default()),
+            replay_gain: ReplayGainMode::Off,
+            loudness: LoudnessCache::load(),
+            track: None,
+            queued: None,
+            stream: None,
+            time_shift: None,
+            opening: None,
+            shuffle: None,
+            repeat: RepeatMode::All,
             initialized: false,
         }
     }
 
-    fn add_to_list(&mut self, media: Media, once: bool) -> bool {
-        match media.src {
-            media::Source::Local(path) => {
-                return self.play_with_file(path, once);
-            } // media::Source::M3u8(_path) => false,
-        }
+    fn add_to_list(&mut self, media: Media, once: bool) -> Result<(), PlayerError> {
+        let item = item_for(media)?;
+        self.push_item(item, once)
     }
 
-    fn play(&mut self, song_index: usize) -> bool {
+    fn play(&mut self, song_index: usize) {
         self.sink.play();
         if let Some(item) = self.play_list.lists.get_mut(song_index) {
-            let status = &mut item.status;
-            match status {
-                PlayStatus::Waiting => {
-                    *status = PlayStatus::Playing(Instant::now(), Duration::from_nanos(0));
-                }
-                PlayStatus::Playing(_, _) => {}
-                PlayStatus::Stopped(duration) => {
-                    *status = PlayStatus::Playing(Instant::now(), *duration);
-                }
-            }
+            item.status = PlayStatus::Playing;
         }
-        true
     }
 
-    fn play_selected(&mut self, selected: usize) -> bool {
-        let len = self.play_list.lists.len();
-        if selected > len {
-            return false;
+    fn play_selected(&mut self, selected: usize) -> Result<(), PlayerError> {
+        if selected >= self.play_list.lists.len() {
+            return Err(PlayerError::NoSuchTrack(selected));
         }
         if self.is_playing() {
             self.stop();
         }
-
-        self.load_new_song(selected)
+        // a pick that won't open moves on as `next` would
+        self.load_playable(selected, |player| {
+            player.next_index(skip_repeat(player.repeat))
+        })
     }
 
-    fn next(&mut self) -> bool {
-        let len = self.play_list.lists.len();
-        if len >= 1 {
-            self.stop();
-            if !self.play_list.lists.is_empty() {
-                if self.selected_song_index + 1 >= len {
-                    self.selected_song_index = 0;
-                } else {
-                    self.selected_song_index += 1
-                }
-                return self.load_new_song(self.selected_song_index);
+    fn next(&mut self) -> Result<(), PlayerError> {
+        // skipping ahead leaves the looped track even in repeat-one
+        match self.next_index(skip_repeat(self.repeat)) {
+            Some(index) => {
+                self.stop();
+                self.load_playable(index, |player| {
+                    player.next_index(skip_repeat(player.repeat))
+                })
             }
-        } else {
             // nothing in playlist
-            return false;
+            None => Ok(()),
         }
-        true
     }
 
-    fn stop(&mut self) -> bool {
+    fn previous(&mut self) -> Result<(), PlayerError> {
+        // the start of a stream is only the oldest audio kept, so a station
+        // always goes back to the entry before it
+        let stream = self.is_stream(self.selected_song_index);
+        if !stream && self.current_time > RESTART_THRESHOLD {
+            return self.seek(Duration::from_secs(0));
+        }
+        match self.previous_index() {
+            Some(index) => {
+                self.stop();
+                self.load_playable(index, MusicPlayer::previous_index)
+            }
+            None if stream => Ok(()),
+            None => self.seek(Duration::from_secs(0)),
+        }
+    }
+
+    fn seek(&mut self, pos: Duration) -> Result<(), PlayerError> {
+        let track = match &self.track {
+            Some(track) => track,
+            None => return Ok(()),
+        };
+        let index = self.selected_song_index;
+        let item = self
+            .play_list
+            .lists
+            .get(index)
+            .ok_or(PlayerError::NoSuchTrack(index))?;
+        if item.stream.is_some() {
+            if let Some(shift) = &self.time_shift {
+                shift.seek(pos);
+                // the track reads from the shift, this only drops what the chain holds
+                track.seek(pos);
+                self.current_time = shift.position();
+            }
+            return Ok(());
+        }
+        let pos = pos.min(item.duration);
+        track.seek(pos);
+        if let Some(seeked) = track.wait_seek(SEEK_WAIT) {
+            seeked.map_err(|err| PlayerError::track(&item.name, err))?;
+            self.current_time = pos;
+        }
+        Ok(())
+    }
+
+    fn seek_by(&mut self, secs: i64) -> Result<(), PlayerError> {
+        let offset = Duration::from_secs(secs.unsigned_abs());
+        let pos = if secs < 0 {
+            self.current_time.saturating_sub(offset)
+        } else {
+            self.current_time.add(offset)
+        };
+        self.seek(pos)
+    }
+
+    fn stop(&mut self) {
         self.sink.stop();
-        true
+        self.fading = None;
     }
 
-    fn pause(&mut self) -> bool {
+    fn pause(&mut self) {
         self.sink.pause();
-        if let Some(item) = self.play_list.lists.first_mut() {
-            let status = &mut item.status;
-            match status {
-                PlayStatus::Waiting => {}
-                PlayStatus::Playing(instant, duration) => {
-                    *status = PlayStatus::Stopped(duration.add(instant.elapsed()));
-                }
-                PlayStatus::Stopped(_) => {}
+        if let Some(fading) = &self.fading {
+            fading.pause();
+        }
+        if let Some(item) = self.play_list.lists.get_mut(self.selected_song_index) {
+            if item.status == PlayStatus::Playing {
+                item.status = PlayStatus::Stopped;
             }
         }
-        true
     }
 
-    fn remove_from_playlist(&mut self, song_index: usize) -> bool {
-        // TODO: fix weird layout after removing from the play list
+    fn remove_from_playlist(&mut self, song_index: usize) -> Result<(), PlayerError> {
+        if song_index >= self.play_list.lists.len() {
+            return Err(PlayerError::NoSuchTrack(song_index));
+        }
+        self.advance_queued();
         self.play_list.lists.remove(song_index);
-        self.play_list.index.select(Some(0));
-        true
+        if let Some(shuffle) = self.shuffle.as_mut() {
+            shuffle.remove(song_index);
+        }
+        if song_index < self.selected_song_index {
+            self.selected_song_index -= 1;
+        } else if song_index == self.selected_song_index {
+            return self.replace_removed();
+        }
+        self.queue_next();
+        Ok(())
     }
 
-    fn resume(&mut self) -> bool {
+    fn resume(&mut self) -> Result<(), PlayerError> {
+        if self.track.is_none() && self.opening.is_none() {
+            return self.load_new_song(self.selected_song_index);
+        }
         self.sink.play();
-        if let Some(item) = self.play_list.lists.first_mut() {
-            let status = &mut item.status;
-            match status {
-                PlayStatus::Waiting => {}
-                PlayStatus::Playing(_, _) => {}
-                PlayStatus::Stopped(duration) => {
-                    *status = PlayStatus::Playing(Instant::now(), *duration);
-                }
+        if let Some(fading) = &self.fading {
+            fading.play();
+        }
+        if let Some(item) = self.play_list.lists.get_mut(self.selected_song_index) {
+            if item.status == PlayStatus::Stopped {
+                item.status = PlayStatus::Playing;
             }
         }
-        true
+        Ok(())
     }
 
     fn get_progress(&self) -> (f32, f32) {
-        (0.0, 0.0)
+        (
+            self.current_time.as_secs_f32(),
+            self.total_time.as_secs_f32(),
+        )
     }
 
     fn is_playing(&self) -> bool {
         self.initialized && !self.sink.is_paused() && !self.play_list.lists.is_empty()
     }
 
-    fn tick(&mut self) {
-        let is_playing = self.is_playing();
-        if let Some(song) = self.play_list.lists.get_mut(self.selected_song_index) {
-            let status = &mut song.status;
-            match status {
-                PlayStatus::Waiting => {
-                    if is_playing {
-                        *status = PlayStatus::Playing(Instant::now(), Duration::from_nanos(0));
-                    }
-                }
-                PlayStatus::Playing(instant, duration) => {
-                    let now = instant.elapsed().add(duration.clone());
-                    if now.ge(&song.duration) {
-                        self.next();
-                    } else {
-                        self.current_time = now;
-                        self.total_time = song.duration.clone();
-                    }
-                }
-                PlayStatus::Stopped(duration) => {
-                    self.current_time = duration.clone();
-                    self.total_time = song.duration.clone();
-                }
+    fn tick(&mut self) -> Result<(), PlayerError> {
+        if self.output.as_ref().is_some_and(|output| output.is_lost()) {
+            self.lose_output();
+            return Err(PlayerError::Device(
+                "output lost, waiting for it".to_string(),
+            ));
+        }
+        if self.output.is_none() && !self.reconnect()? {
+            return Ok(());
+        }
+        self.finish_connecting()?;
+        self.advance_queued();
+        self.apply_gain();
+        self.update_stream();
+        if self.fading.as_ref().is_some_and(|fading| fading.empty()) {
+            self.fading = None;
+        }
+        let crossfade = self.crossfade;
+        let track = self.track.clone();
+        if let Some(song) = self.play_list.lists.get(self.selected_song_index) {
+            let track = match track {
+                Some(track) => track,
+                None => return Ok(()),
+            };
+            let now = track.position();
+            let total = song.duration;
+            let fade = crossfade.min(total / 2);
+            // the queued track takes over by itself once this one drains
+            if track.is_finished() && self.queued.is_none() {
+                return self.track_ended();
+            } else if let (Some(_), Some(shift)) = (song.stream, &self.time_shift) {
+                // a stream's clock runs from when it was opened to its live edge
+                self.current_time = shift.position();
+                self.total_time = shift.live();
+            } else if fade.is_zero() || now.add(fade).lt(&total) || !self.start_crossfade(fade) {
+                self.current_time = now.min(total);
+                self.total_time = total;
             }
         } else {
             if self.play_list.lists.is_empty() {
                 self.stop();
             }
         }
+        Ok(())
     }
 
     fn volume(&self) -> f32 {
         self.sink.volume()
     }
 
-    fn set_volume(&mut self, new_volume: f32) -> bool {
+    fn set_volume(&mut self, new_volume: f32) {
         self.sink.set_volume(new_volume);
-        true
+        if let Some(fading) = &self.fading {
+            fading.set_volume(new_volume);
+        }
     }
 
-    fn load_new_song(&mut self, index: usize) -> bool {
-        if let Some(item) = self.play_list.lists.get_mut(index) {
-            item.status = PlayStatus::Waiting;
-            let f = File::open(item.path.as_str()).unwrap();
-            let buf_reader = BufReader::new(f);
-            let (stream, stream_handle) = OutputStream::try_default().unwrap();
-            self.stream = stream;
-            self.stream_handle = stream_handle;
-            let volume = self.volume();
-            self.sink = Sink::try_new(&self.stream_handle).unwrap();
-            self.set_volume(volume);
-            self.sink.append(Decoder::new(buf_reader).unwrap());
-            self.selected_song_index = index;
-            self.play(index);
+    fn set_crossfade(&mut self, crossfade: Duration) {
+        self.crossfade = crossfade;
+        self.queue_next();
+    }
+
+    fn repeat(&self) -> RepeatMode {
+        self.repeat
+    }
+
+    fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+        self.queue_next();
+    }
+
+    fn rate(&self) -> f32 {
+        self.rate.get()
+    }
+
+    fn set_rate(&mut self, rate: f32) {
+        self.rate.set(rate.clamp(MIN_RATE, MAX_RATE));
+    }
+
+    fn set_replay_gain(&mut self, mode: ReplayGainMode) {
+        self.replay_gain = mode;
+        for index in 0..self.play_list.lists.len() {
+            self.measure_later(index);
         }
+        self.apply_gain();
+    }
+
+    fn equalizer(&self) -> [f32; BANDS] {
+        self.equalizer.gains()
+    }
+
+    fn set_equalizer(&mut self, gains: [f32; BANDS]) {
+        self.equalizer.set_gains(gains);
+    }
+
+    fn is_shuffle(&self) -> bool {
+        self.shuffle.is_some()
+    }
+
+    fn set_shuffle(&mut self, shuffle: bool) {
+        self.shuffle = if shuffle {
+            let current = self.track.as_ref().map(|_| self.selected_song_index);
+            Some(ShuffleOrder::new(self.play_list.lists.len(), current))
+        } else {
+            None
+        };
+        self.queue_next();
+    }
+
+    fn output_device(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
 
-        false
+    fn set_output_device(&mut self, device: Option<String>) -> Result<(), PlayerError> {
+        let output =
+            Output::open(&OutputBackend::Cpal, device.as_deref()).map_err(PlayerError::device)?;
+        self.backend = OutputBackend::Cpal;
+        self.device = device;
+        let play = self.is_playing();
+        self.attach_output(output, play)
+    }
+
+    fn load_new_song(&mut self, index: usize) -> Result<(), PlayerError> {
+        let item = self
+            .play_list
+            .lists
+            .get_mut(index)
+            .ok_or(PlayerError::NoSuchTrack(index))?;
+        item.status = PlayStatus::Waiting;
+        // a station still connecting was for the entry played before
+        self.opening = None;
+        if item.stream.is_some() {
+            return self.connect(index);
+        }
+        let (track, control) = match self.open_track(index) {
+            Ok(track) => track,
+            Err(err) => {
+                // what played before may already be stopped, and won't finish
+                self.stop();
+                self.sink.pause();
+                self.track = None;
+                if let Some((_, control)) = self.queued.take() {
+                    control.cancel();
+                }
+                self.current_time = Duration::from_secs(0);
+                self.total_time = Duration::from_secs(0);
+                return Err(err);
+            }
+        };
+        let volume = self.volume();
+        self.fading = None;
+        self.sink = sink_on(self.output.as_ref());
+        self.set_volume(volume);
+        self.sink.append(track);
+        self.track = Some(control);
+        self.queued = None;
+        self.select_song(index);
+        self.play(index);
+        self.queue_next();
+        Ok(())
     }
 }
 
 impl MusicPlayer {
-    pub fn playing_song(&self) -> Option<&PlayListItem> {
-        self.play_list.lists.get(self.selected_song_index)
+    pub fn selected_song_index(&self) -> usize {
+        self.selected_song_index
+    }
+
+    pub fn spectrum(&self) -> Arc<Spectrum> {
+        self.spectrum.clone()
+    }
+
+    pub fn has_track(&self) -> bool {
+        self.track.is_some()
+    }
+
+    pub fn has_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    /// Whether the playing stream is waiting for audio to arrive.
+    pub fn is_buffering(&self) -> bool {
+        self.track
+            .as_ref()
+            .is_some_and(|track| track.is_buffering())
+    }
+
+    /// How far behind live the playing stream is, and how far back it can
+    /// go, in seconds. Zero for both when no stream plays.
+    pub fn time_shift(&self) -> (f32, f32) {
+        let station = self.track.is_some() && self.is_stream(self.selected_song_index);
+        match &self.time_shift {
+            Some(shift) if station => {
+                let live = shift.live();
+                (
+                    live.saturating_sub(shift.position()).as_secs_f32(),
+                    live.saturating_sub(shift.oldest()).as_secs_f32(),
+                )
+            }
+            _ => (0.0, 0.0),
+        }
     }
 
-    fn play_with_file(&mut self, path: String, once: bool) -> bool {
-        let duration: Duration;
-        if path.ends_with(".mp3") {
-            let dur = mp3_duration::from_path(path.clone());
-            match dur {
-                Ok(dur) => {
-                    duration = dur;
+    /// Replaces the playlist with the one in an M3U file and plays it.
+    /// Entries that won't open are left out, and the first one's error is
+    /// returned once the rest are in.
+    pub fn load_playlist(&mut self, path: &Path) -> Result<(), PlayerError> {
+        let entries = m3u::read(path).map_err(|err| PlayerError::Playlist(err.to_string()))?;
+        let mut first_error = None;
+        let mut added = false;
+        for entry in entries {
+            let mut item = match item_for(entry.media) {
+                Ok(item) => item,
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                    continue;
                 }
+            };
+            if let Some(title) = entry.title {
+                item.name = title;
+            }
+            self.insert_item(item, !added);
+            added = true;
+        }
+        if !added {
+            return Err(first_error
+                .unwrap_or_else(|| PlayerError::Playlist("nothing to play in it".to_string())));
+        }
+        // opened once the whole list is in, so the next track is queued once
+        let loaded = self.load_playable(0, |player| player.next_index(skip_repeat(player.repeat)));
+        for result in [loaded, self.tick()] {
+            if let Err(err) = result {
+                first_error.get_or_insert(err);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Writes the playlist to `path` as extended M3U.
+    pub fn save_playlist(&self, path: &Path) -> Result<(), PlayerError> {
+        m3u::write(path, &self.play_list.lists)
+            .map_err(|err| PlayerError::Playlist(err.to_string()))
+    }
+
+    /// Starts recording the playing station into `dir`, or stops with
+    /// `None`. Returns the file the recording starts in.
+    pub fn record(&mut self, dir: Option<PathBuf>) -> Result<Option<PathBuf>, PlayerError> {
+        let dir = match dir {
+            Some(dir) => dir,
+            None => {
+                if let Some(tap) = &self.stream {
+                    tap.stop_recording();
+                }
+                return Ok(None);
+            }
+        };
+        let station = self
+            .play_list
+            .lists
+            .get(self.selected_song_index)
+            .filter(|item| item.stream.is_some() && self.track.is_some());
+        match (station, &self.stream) {
+            (Some(station), Some(tap)) => tap
+                .start_recording(&dir, &station.name)
+                .map(Some)
+                .map_err(|err| PlayerError::Record(err.to_string())),
+            _ => Err(PlayerError::Record("no station is playing".to_string())),
+        }
+    }
+
+    /// The file the playing station is being recorded to.
+    pub fn recording(&self) -> Option<PathBuf> {
+        let station = self
+            .play_list
+            .lists
+            .get(self.selected_song_index)
+            .is_some_and(|item| item.stream.is_some());
+        self.stream
+            .as_ref()
+            .filter(|_| station)
+            .and_then(|tap| tap.recording())
+    }
+
+    /// Plays `media` at once, from its place on the playlist if it is on it
+    /// already, else after adding it. Does nothing if it is already playing.
+    pub fn play_now(&mut self, media: Media) -> Result<(), PlayerError> {
+        let (path, stream) = match &media.src {
+            media::Source::Local(path) => (path.clone(), None),
+            media::Source::M3u8(radio) => (radio.url.clone(), Some(StreamKind::Hls)),
+            media::Source::Http(radio) => (radio.url.clone(), Some(StreamKind::Http)),
+        };
+        let found = self
+            .play_list
+            .lists
+            .iter()
+            .position(|item| item.path == path && item.stream == stream);
+        let index = match found {
+            Some(index) => index,
+            None => {
+                self.add_to_list(media, false)?;
+                self.play_list.lists.len() - 1
+            }
+        };
+        let loaded = self.track.is_some() || self.opening.is_some();
+        if index == self.selected_song_index && loaded {
+            return self.resume();
+        }
+        self.play_selected(index)
+    }
+
+    /// Moves playback onto a newly opened output. The old sinks went away
+    /// with the old stream, so the current track is reloaded where it was.
+    fn attach_output(&mut self, output: Output, play: bool) -> Result<(), PlayerError> {
+        let pos = self.current_time;
+        self.output = Some(output);
+        if self.track.is_some() {
+            self.load_new_song(self.selected_song_index)?;
+            self.seek(pos)?;
+            if !play {
+                self.pause();
+            }
+        } else {
+            let sink = sink_on(self.output.as_ref());
+            sink.set_volume(self.volume());
+            if self.sink.is_paused() {
+                sink.pause();
+            }
+            self.sink = sink;
+        }
+        Ok(())
+    }
+
+    /// Drops a failed output. Playback state is kept as it was, with the
+    /// clock held by `tick`, until `reconnect` finds a device again.
+    fn lose_output(&mut self) {
+        self.output = None;
+        self.fading = None;
+        if let Some((_, control)) = self.queued.take() {
+            control.cancel();
+        }
+        let sink = sink_on(None);
+        sink.set_volume(self.volume());
+        if self.sink.is_paused() {
+            sink.pause();
+        }
+        self.sink = sink;
+    }
+
+    /// Whether an output is back. Failing to open one is not an error here,
+    /// the header already says there is no device.
+    fn reconnect(&mut self) -> Result<bool, PlayerError> {
+        if self.last_reconnect.elapsed() < RECONNECT_INTERVAL {
+            return Ok(false);
+        }
+        self.last_reconnect = Instant::now();
+        match Output::open(&self.backend, self.device.as_deref()) {
+            Ok(output) => {
+                let play = self.is_playing();
+                self.attach_output(output, play)?;
+                Ok(true)
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Builds the source chain the sink plays for a file in the playlist,
+    /// marking the entry unplayable if it won't open. Stations are opened by
+    /// `connect` instead.
+    fn open_track(
+        &mut self,
+        index: usize,
+    ) -> Result<(TrackSource, Arc<TrackControl>), PlayerError> {
+        let item = self
+            .play_list
+            .lists
+            .get_mut(index)
+            .ok_or(PlayerError::NoSuchTrack(index))?;
+        let (track, control) = match Track::open(item.path.as_str()) {
+            Ok(track) => track,
+            Err(err) => {
+                item.unplayable = true;
+                return Err(PlayerError::track(&item.name, err));
+            }
+        };
+        item.unplayable = false;
+        control.set_gain(self.gain_for(index));
+        let track = self.chain(track, &control);
+        Ok((track, control))
+    }
+
+    /// Puts a track behind the effects every track plays through.
+    fn chain(&self, track: Track, control: &Arc<TrackControl>) -> TrackSource {
+        let track = TimeStretch::new(track, self.rate.clone(), control.clone());
+        let track = Equalizer::new(track, self.equalizer.clone());
+        let track = SpectrumTap::new(track, self.spectrum.clone());
+        Played::new(track, control.clone(), self.rate.clone())
+    }
+
+    /// Starts connecting to the station at `index` on a thread of its own,
+    /// as a slow or dead one can take many seconds to answer. What played
+    /// before stops now, and `tick` starts the station once it is open.
+    fn connect(&mut self, index: usize) -> Result<(), PlayerError> {
+        let item = self
+            .play_list
+            .lists
+            .get(index)
+            .ok_or(PlayerError::NoSuchTrack(index))?;
+        let (kind, url) = (item.stream, item.path.clone());
+        // a recording belongs to the station it was started on
+        if let Some(old) = self.stream.take() {
+            old.stop_recording();
+        }
+        let tap = Arc::new(StreamTap::new());
+        let shift = Arc::new(TimeShift::new());
+        self.stream = Some(tap.clone());
+        self.time_shift = Some(shift.clone());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let opened = match kind {
+                Some(StreamKind::Hls) => hls::open(&url, tap),
+                _ => icy::open(&url, tap),
+            }
+            .and_then(|format| Track::live(format, shift));
+            // nobody receives it once the player moved on
+            let _ = sender.send(opened);
+        });
+        self.opening = Some(receiver);
+
+        let volume = self.volume();
+        self.fading = None;
+        self.sink = sink_on(self.output.as_ref());
+        self.set_volume(volume);
+        self.track = None;
+        self.queued = None;
+        self.current_time = Duration::from_secs(0);
+        self.total_time = Duration::from_secs(0);
+        self.select_song(index);
+        self.play(index);
+        Ok(())
+    }
+
+    /// Plays the station `connect` opened, once it has. A station that
+    /// couldn't be opened leaves nothing playing.
+    fn finish_connecting(&mut self) -> Result<(), PlayerError> {
+        let opened = match self.opening.as_ref().map(Receiver::try_recv) {
+            None | Some(Err(TryRecvError::Empty)) => return Ok(()),
+            Some(Ok(opened)) => Some(opened),
+            Some(Err(TryRecvError::Disconnected)) => None,
+        };
+        self.opening = None;
+        let index = self.selected_song_index;
+        let item = self
+            .play_list
+            .lists
+            .get_mut(index)
+            .ok_or(PlayerError::NoSuchTrack(index))?;
+        let (track, control) = match opened {
+            Some(Ok(track)) => track,
+            failed => {
+                item.unplayable = true;
+                item.status = PlayStatus::Waiting;
+                let err = match failed {
+                    Some(Err(err)) => PlayerError::track(&item.name, err),
+                    _ => PlayerError::track(&item.name, Error::Unsupported("connection lost")),
+                };
+                self.sink.pause();
+                return Err(err);
+            }
+        };
+        item.unplayable = false;
+        item.sample_rate = track.sample_rate();
+        item.channels = track.channels();
+        control.set_gain(self.gain_for(index));
+        let track = self.chain(track, &control);
+        self.sink.append(track);
+        self.track = Some(control);
+        Ok(())
+    }
+
+    fn gain_for(&self, index: usize) -> f32 {
+        match self.play_list.lists.get(index) {
+            // there is no file to measure, nor tags to read
+            Some(item) if item.stream.is_some() => 1.0,
+            Some(item) => item
+                .replay_gain
+                .factor(self.replay_gain, || self.loudness.get(item.path.as_str())),
+            None => 1.0,
+        }
+    }
+
+    /// Keeps the gain of the playing and queued tracks in step with the mode
+    /// and with loudness measurements that finish while they play.
+    fn apply_gain(&self) {
+        // the track asked for last is measured first, so that's the playing one
+        if let Some((index, queued)) = &self.queued {
+            queued.set_gain(self.gain_for(*index));
+        }
+        if let Some(track) = &self.track {
+            track.set_gain(self.gain_for(self.selected_song_index));
+        }
+    }
+
+    /// Starts measuring the entry at `index` if the ReplayGain mode will need
+    /// it, so its gain is known before it plays rather than found midway.
+    fn measure_later(&self, index: usize) {
+        if let Some(item) = self.play_list.lists.get(index) {
+            if item.stream.is_none() && item.replay_gain.needs_measuring(self.replay_gain) {
+                self.loudness.measure_later(&item.path);
+            }
+        }
+    }
+
+    /// Stops the track whose entry was removed and loads the one that took
+    /// its place, paused if it was paused. Past the end of the playlist
+    /// nothing is loaded.
+    fn replace_removed(&mut self) -> Result<(), PlayerError> {
+        let had_track = self.track.is_some() || self.opening.take().is_some();
+        let play = self.is_playing();
+        self.stop();
+        self.sink.pause();
+        if let Some((_, control)) = self.queued.take() {
+            control.cancel();
+        }
+        self.track = None;
+        self.current_time = Duration::from_secs(0);
+        self.total_time = Duration::from_secs(0);
+        let index = self.selected_song_index;
+        if index >= self.play_list.lists.len() {
+            self.selected_song_index = 0;
+            return Ok(());
+        }
+        self.select_song(index);
+        if !had_track {
+            return Ok(());
+        }
+        let result = self.load_playable(index, |player| {
+            player.next_index(skip_repeat(player.repeat))
+        });
+        if !play {
+            self.pause();
+        }
+        result
+    }
+
+    /// Decodes the track after the current one and appends it to the sink,
+    /// so it starts the moment the current one runs out.
+    fn queue_next(&mut self) {
+        // a queued track the sink already started is the one playing now,
+        // even if `tick` hasn't caught up with it yet
+        self.advance_queued();
+        if let Some((_, control)) = self.queued.take() {
+            control.cancel();
+        }
+        if self.track.is_none() || !self.crossfade.is_zero() {
+            return;
+        }
+        let index = match self.next_index(self.repeat) {
+            Some(index) => index,
+            None => return,
+        };
+        // an unplayable track is left for `track_ended` to skip, and a
+        // stream is opened once reached, so it starts at the live edge
+        if self.is_unplayable(index) || self.is_stream(index) {
+            return;
+        }
+        if let Ok((track, control)) = self.open_track(index) {
+            self.sink.append(track);
+            self.queued = Some((index, control));
+        }
+    }
+
+    /// Starts the next track on a fresh sink while the current one fades out
+    /// on the old sink, which is kept alive until it drains.
+    fn start_crossfade(&mut self, fade: Duration) -> bool {
+        let index = match self.next_index(self.repeat) {
+            Some(index) => index,
+            None => return false,
+        };
+        if self.is_unplayable(index) || self.is_stream(index) {
+            return false;
+        }
+        let (track, control) = match self.open_track(index) {
+            Ok(track) => track,
+            Err(_) => return false,
+        };
+        let sink = sink_on(self.output.as_ref());
+        sink.set_volume(self.volume());
+        sink.append(track.fade_in(fade));
+        if let Some(current) = &self.track {
+            current.fade_out(fade);
+        }
+        self.fading = Some(std::mem::replace(&mut self.sink, sink));
+        if let Some(item) = self.play_list.lists.get_mut(self.selected_song_index) {
+            item.status = PlayStatus::Waiting;
+        }
+        if let Some(item) = self.play_list.lists.get_mut(index) {
+            item.status = PlayStatus::Playing;
+        }
+        self.select_song(index);
+        self.track = Some(control);
+        true
+    }
+
+    /// Moves on once the current track has played out, or stops cleanly
+    /// when repeat is off and the playlist is used up.
+    fn track_ended(&mut self) -> Result<(), PlayerError> {
+        self.stop();
+        let result = match self.next_index(self.repeat) {
+            Some(index) => self.load_playable(index, |player| {
+                player.next_index(skip_repeat(player.repeat))
+            }),
+            None => Ok(()),
+        };
+        // a station being connected to has nothing in the sink yet
+        if self.sink.empty() && self.opening.is_none() {
+            self.sink.pause();
+            self.track = None;
+            if let Some(item) = self.play_list.lists.get_mut(self.selected_song_index) {
+                item.status = PlayStatus::Waiting;
+            }
+            self.current_time = Duration::from_secs(0);
+        }
+        result
+    }
+
+    /// Loads `index`, or if it won't open, whichever track `step` leads to
+    /// first that does. Every entry is tried at most once. The first error
+    /// is returned even when a later track plays.
+    fn load_playable<F>(&mut self, mut index: usize, step: F) -> Result<(), PlayerError>
+    where
+        F: Fn(&mut Self) -> Option<usize>,
+    {
+        let mut error = None;
+        for _ in 0..self.play_list.lists.len() {
+            match self.load_new_song(index) {
+                Ok(()) => break,
                 Err(err) => {
-                    duration = err.at_duration;
-                    if duration.is_zero() {
-                        return false;
+                    error.get_or_insert(err);
+                    self.select_song(index);
+                    match step(self) {
+                        Some(next) => index = next,
+                        None => break,
                     }
                 }
             }
+        }
+        error.map_or(Ok(()), Err)
+    }
+
+    fn is_unplayable(&self, index: usize) -> bool {
+        self.play_list
+            .lists
+            .get(index)
+            .is_some_and(|item| item.unplayable)
+    }
+
+    fn is_stream(&self, index: usize) -> bool {
+        self.play_list
+            .lists
+            .get(index)
+            .is_some_and(|item| item.stream.is_some())
+    }
+
+    /// Shows the song the playing station announced last, and ends its
+    /// recording once something else plays.
+    fn update_stream(&mut self) {
+        let tap = match &self.stream {
+            Some(tap) => tap,
+            None => return,
+        };
+        match self.play_list.lists.get_mut(self.selected_song_index) {
+            Some(item) if item.stream.is_some() => item.title = tap.title(),
+            _ => tap.stop_recording(),
+        }
+    }
+
+    /// The playlist index that follows the current one in play order, or
+    /// `None` once the playlist has run out with repeat off.
+    fn next_index(&mut self, repeat: RepeatMode) -> Option<usize> {
+        let len = self.play_list.lists.len();
+        if len == 0 {
+            return None;
+        }
+        let current = self.selected_song_index;
+        match (repeat, self.shuffle.as_mut()) {
+            (RepeatMode::One, _) => Some(current.min(len - 1)),
+            (RepeatMode::Off, Some(shuffle)) if shuffle.is_exhausted() => None,
+            (_, Some(shuffle)) => shuffle.peek_next(current),
+            (RepeatMode::Off, None) if current + 1 >= len => None,
+            (_, None) => Some((current + 1) % len),
+        }
+    }
+
+    /// The playlist index played before the current one, following the
+    /// shuffle history when shuffle is on.
+    fn previous_index(&mut self) -> Option<usize> {
+        let len = self.play_list.lists.len();
+        if len == 0 {
+            return None;
+        }
+        let current = self.selected_song_index.min(len - 1);
+        match (self.repeat, self.shuffle.as_mut()) {
+            (_, Some(shuffle)) => shuffle.back(),
+            (RepeatMode::Off, None) if current == 0 => None,
+            (_, None) if current == 0 => Some(len - 1),
+            (_, None) => Some(current - 1),
+        }
+    }
+
+    fn select_song(&mut self, index: usize) {
+        self.selected_song_index = index;
+        if let Some(shuffle) = self.shuffle.as_mut() {
+            shuffle.select(index);
+        }
+    }
+
+    /// Moves the play state over to the queued track once the sink has reached it.
+    fn advance_queued(&mut self) {
+        if !self
+            .queued
+            .as_ref()
+            .is_some_and(|(_, control)| control.is_started())
+        {
+            return;
+        }
+        let (index, control) = self.queued.take().unwrap();
+        if let Some(item) = self.play_list.lists.get_mut(self.selected_song_index) {
+            item.status = PlayStatus::Waiting;
+        }
+        if let Some(item) = self.play_list.lists.get_mut(index) {
+            item.status = PlayStatus::Playing;
+        }
+        self.select_song(index);
+        self.track = Some(control);
+        self.queue_next();
+    }
+
+    /// Appends `item` to the playlist, or with `once`, or to an empty
+    /// playlist, replaces the playlist with it and starts playing it.
+    fn push_item(&mut self, item: PlayListItem, once: bool) -> Result<(), PlayerError> {
+        let start = once || self.play_list.lists.is_empty();
+        self.insert_item(item, start);
+        if start {
+            self.load_new_song(0)?;
         } else {
-            // if let Ok(f) = File::open(path.as_str()) {
-            //     let dec = Decoder::new(f);
-            //     if let Ok(dec) = dec {
-            //         if let Some(dur) = dec.total_duration() {
-            //             duration = dur;
-            //         } else {
-            //             return false;
-            //         }
-            //     } else {
-            //         return false;
-            //     }
-            // } else {
-            //     return false;
-            // }
-            return true;
-        }
-        match File::open(path.as_str()) {
-            Ok(f) => {
-                let path = Path::new(path.as_str());
-                let file_name = path.file_name().unwrap().to_string_lossy().to_string();
-                if once || self.play_list.lists.is_empty() {
-                    self.stop();
-                    let buf_reader = BufReader::new(f);
-                    let sink = self.stream_handle.play_once(buf_reader).unwrap();
-                    self.sink = sink;
-                    self.play_list.lists.clear();
-                }
-                self.play_list.lists.push(PlayListItem {
-                    name: file_name,
-                    duration,
-                    current_pos: Duration::from_secs(0),
-                    status: PlayStatus::Waiting,
-                    path: path.to_string_lossy().to_string(),
-                });
-                if !self.initialized {
-                    self.initialized = true;
-                }
-                self.play(self.selected_song_index);
-                self.tick();
-                return true;
+            self.play(self.selected_song_index);
+            self.queue_next();
+        }
+        self.tick()
+    }
+
+    /// Adds `item` to the end of the playlist, or with `replace`, stops
+    /// playback and makes it the whole playlist. Nothing is opened.
+    fn insert_item(&mut self, item: PlayListItem, replace: bool) {
+        if replace {
+            self.stop();
+            self.play_list.lists.clear();
+            if self.shuffle.is_some() {
+                self.shuffle = Some(ShuffleOrder::new(0, None));
             }
-            Err(_) => false,
         }
+        self.play_list.lists.push(item);
+        let index = self.play_list.lists.len() - 1;
+        if let Some(shuffle) = self.shuffle.as_mut() {
+            shuffle.insert(index);
+        }
+        self.measure_later(index);
+        if !self.initialized {
+            self.initialized = true;
+        }
+    }
+}
+
+fn item_for(media: Media) -> Result<PlayListItem, PlayerError> {
+    match media.src {
+        media::Source::Local(path) => file_item(path),
+        media::Source::M3u8(radio) => Ok(stream_item(radio, StreamKind::Hls)),
+        media::Source::Http(radio) => Ok(stream_item(radio, StreamKind::Http)),
+    }
+}
+
+fn file_item(path: String) -> Result<PlayListItem, PlayerError> {
+    let file_name = Path::new(path.as_str())
+        .file_name()
+        .map_or_else(|| path.clone(), |name| name.to_string_lossy().to_string());
+    match probe(path.as_str()) {
+        Ok(info) => Ok(PlayListItem {
+            name: file_name,
+            duration: info.duration,
+            sample_rate: info.sample_rate,
+            channels: info.channels,
+            bitrate: info.bitrate,
+            replay_gain: info.replay_gain,
+            current_pos: Duration::from_secs(0),
+            status: PlayStatus::Waiting,
+            path,
+            unplayable: false,
+            stream: None,
+            title: None,
+        }),
+        Err(err) => Err(PlayerError::track(&file_name, err)),
+    }
+}
+
+/// A radio station's entry. It isn't opened until it plays, as a stream
+/// can't be probed without starting it.
+fn stream_item(radio: RadioConfig, kind: StreamKind) -> PlayListItem {
+    PlayListItem {
+        name: radio.name,
+        duration: Duration::from_secs(0),
+        sample_rate: 0,
+        channels: 0,
+        bitrate: 0,
+        replay_gain: ReplayGain::default(),
+        current_pos: Duration::from_secs(0),
+        status: PlayStatus::Waiting,
+        path: radio.url,
+        unplayable: false,
+        stream: Some(kind),
+        title: None,
+    }
+}
+
+/// Repeat-one loops the track when it ends, but stepping past a track that
+/// won't play, or skipping by hand, moves on as repeat-all would.
+fn skip_repeat(repeat: RepeatMode) -> RepeatMode {
+    match repeat {
+        RepeatMode::One => RepeatMode::All,
+        repeat => repeat,
+    }
+}
+
+/// A sink playing on `output`, or one that holds its sources until there is one.
+fn sink_on(output: Option<&Output>) -> Sink {
+    match output {
+        Some(output) => output.new_sink(),
+        None => Sink::new_idle().0,
     }
 }
 
 impl Drop for MusicPlayer {
     fn drop(&mut self) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::media::Source as MediaSource;
+
+    fn bundled_song() -> Media {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/music/Chelsea Grin - Dead Rose.mp3"
+        );
+        Media {
+            src: MediaSource::Local(path.to_string()),
+        }
+    }
+
+    /// Ticks for `time`, as the engine loop would.
+    fn run(player: &mut MusicPlayer, time: Duration) {
+        let until = Instant::now() + time;
+        while Instant::now() < until {
+            player.tick().unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn player_with(songs: usize) -> MusicPlayer {
+        let mut player = MusicPlayer::new(OutputBackend::Null);
+        for _ in 0..songs {
+            player.add_to_list(bundled_song(), false).unwrap();
+        }
+        player
+    }
+
+    #[test]
+    fn playing_advances_the_position() {
+        let mut player = player_with(1);
+        assert!(player.has_output());
+        assert!(player.is_playing());
+        let (start, total) = player.get_progress();
+        assert!(total > 60.0, "the song is {}s long", total);
+
+        run(&mut player, Duration::from_millis(600));
+
+        let (pos, _) = player.get_progress();
+        assert!(
+            pos > start + 0.3,
+            "position {} after starting at {}",
+            pos,
+            start
+        );
+        assert_eq!(player.play_list.lists[0].status, PlayStatus::Playing);
+    }
+
+    #[test]
+    fn paused_player_holds_its_position() {
+        let mut player = player_with(1);
+        run(&mut player, Duration::from_millis(300));
+        player.pause();
+        let (paused, _) = player.get_progress();
+
+        run(&mut player, Duration::from_millis(400));
+
+        assert!(!player.is_playing());
+        assert!(player.get_progress().0 - paused < 0.1);
+    }
+
+    #[test]
+    fn finished_track_moves_on_to_the_next() {
+        let mut player = player_with(2);
+        let (_, total) = player.get_progress();
+        player.seek(Duration::from_secs_f32(total - 0.5)).unwrap();
+
+        run(&mut player, Duration::from_millis(1500));
+
+        assert_eq!(player.selected_song_index(), 1);
+        assert_eq!(player.play_list.lists[0].status, PlayStatus::Waiting);
+        assert_eq!(player.play_list.lists[1].status, PlayStatus::Playing);
+        let (pos, _) = player.get_progress();
+        assert!(pos > 0.0 && pos < 1.5, "next track at {}", pos);
+    }
+
+    #[test]
+    fn end_of_playlist_stops_with_repeat_off() {
+        let mut player = player_with(2);
+        player.set_repeat(RepeatMode::Off);
+        player.next().unwrap();
+        assert_eq!(player.selected_song_index(), 1);
+        let (_, total) = player.get_progress();
+        player.seek(Duration::from_secs_f32(total - 0.5)).unwrap();
+
+        run(&mut player, Duration::from_millis(1500));
+
+        assert_eq!(player.selected_song_index(), 1);
+        assert!(!player.has_track());
+        assert_eq!(player.get_progress().0, 0.0);
+    }
+
+    #[test]
+    fn end_of_playlist_wraps_with_repeat_all() {
+        let mut player = player_with(2);
+        player.next().unwrap();
+        let (_, total) = player.get_progress();
+        player.seek(Duration::from_secs_f32(total - 0.5)).unwrap();
+
+        run(&mut player, Duration::from_millis(1500));
+
+        assert_eq!(player.selected_song_index(), 0);
+        assert!(player.is_playing());
+        assert_eq!(player.play_list.lists[0].status, PlayStatus::Playing);
+    }
+
+    fn station() -> Media {
+        // nothing listens there, the station is only selected, never heard
+        Media {
+            src: MediaSource::Http(RadioConfig {
+                name: "Station".to_string(),
+                url: "http://127.0.0.1:9/".to_string(),
+                genres: vec![],
+                favourite: false,
+                comments: vec![],
+            }),
+        }
+    }
+
+    #[test]
+    fn previous_restarts_a_song_played_a_while() {
+        let mut player = player_with(2);
+        player.next().unwrap();
+        player.current_time = RESTART_THRESHOLD * 2;
+
+        player.previous().unwrap();
+
+        assert_eq!(player.selected_song_index(), 1);
+        assert_eq!(player.get_progress().0, 0.0);
+    }
+
+    #[test]
+    fn previous_leaves_a_station_however_long_it_played() {
+        let mut player = player_with(1);
+        player.add_to_list(station(), false).unwrap();
+        player.play_selected(1).unwrap();
+        player.current_time = RESTART_THRESHOLD * 2;
+
+        player.previous().unwrap();
+
+        assert_eq!(player.selected_song_index(), 0);
+        assert!(player.has_track());
+        assert!(!player.is_stream(player.selected_song_index()));
+    }
+
+    #[test]
+    fn loaded_playlist_plays_its_first_entry_and_queues_the_next() {
+        let song = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/music/Chelsea Grin - Dead Rose.mp3"
+        );
+        let path = std::env::temp_dir().join(format!("load-{}.m3u", std::process::id()));
+        let list = format!(
+            "#EXTM3U\n#EXTINF:1,One\n{0}\nmissing.mp3\n#EXTINF:1,Two\n{0}\n",
+            song
+        );
+        std::fs::write(&path, list).unwrap();
+        let mut player = player_with(1);
+
+        let loaded = player.load_playlist(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        // the missing file is reported, the rest still plays
+        assert!(loaded.is_err());
+        let names: Vec<_> = player
+            .play_list
+            .lists
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect();
+        assert_eq!(names, ["One", "Two"]);
+        assert_eq!(player.selected_song_index(), 0);
+        assert!(player.is_playing());
+        assert!(player.queued.as_ref().is_some_and(|(index, _)| *index == 1));
+    }
+}
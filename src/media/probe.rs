@@ -0,0 +1,275 @@
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek},
+    path::Path,
+    time::Duration,
+};
+
+use symphonia::core::{
+    codecs::{CodecParameters, CodecRegistry, CODEC_TYPE_OPUS},
+    errors::Error,
+    formats::{FormatOptions, FormatReader},
+    io::{MediaSource, MediaSourceStream},
+    meta::MetadataOptions,
+    probe::{Hint, ProbeResult},
+};
+
+use super::{
+    replay_gain::ReplayGain,
+    ts::{self, TsDemuxer},
+};
+
+pub struct AudioInfo {
+    pub duration: Duration,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Average bitrate in kbit/s, derived from the file size.
+    pub bitrate: u32,
+    pub replay_gain: ReplayGain,
+}
+
+/// The decoders tracks are opened with: symphonia's own, and Opus when the
+/// `opus` feature is on.
+pub fn codecs() -> &'static CodecRegistry {
+    #[cfg(feature = "opus")]
+    {
+        static CODECS: std::sync::OnceLock<CodecRegistry> = std::sync::OnceLock::new();
+        CODECS.get_or_init(|| {
+            let mut registry = CodecRegistry::new();
+            symphonia::default::register_enabled_codecs(&mut registry);
+            registry.register_all::<super::opus::OpusDecoder>();
+            registry
+        })
+    }
+    #[cfg(not(feature = "opus"))]
+    symphonia::default::get_codecs()
+}
+
+/// Opens `path` with whichever demuxer recognises it.
+pub fn open_format(path: &str) -> Result<Box<dyn FormatReader>, Error> {
+    Ok(probe_format(path)?.format)
+}
+
+fn probe_format(path: &str) -> Result<ProbeResult, Error> {
+    let mut f = File::open(path)?;
+    let mut hint = Hint::new();
+    let source: Box<dyn MediaSource> = if is_transport_stream(&mut f)? {
+        // symphonia has no MPEG-TS reader, so the audio is taken out first
+        let (audio, kind) = ts::demux_all(f)?;
+        let kind = kind.ok_or(Error::Unsupported("no audio stream in transport stream"))?;
+        hint.with_extension(kind.extension());
+        Box::new(Cursor::new(audio))
+    } else {
+        if let Some(ext) = Path::new(path).extension() {
+            hint.with_extension(&ext.to_string_lossy());
+        }
+        Box::new(f)
+    };
+    let mss = MediaSourceStream::new(source, Default::default());
+    symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        },
+        &MetadataOptions::default(),
+    )
+}
+
+/// Whether `f` starts with transport stream packets. Leaves it at the start.
+fn is_transport_stream(f: &mut File) -> io::Result<bool> {
+    let mut start = vec![];
+    f.by_ref()
+        .take(2 * ts::PACKET as u64)
+        .read_to_end(&mut start)?;
+    f.rewind()?;
+    Ok(TsDemuxer::detect(&start))
+}
+
+/// Picks the first track there is a decoder for.
+pub fn audio_track(format: &dyn FormatReader) -> Result<(u32, CodecParameters), Error> {
+    format
+        .tracks()
+        .iter()
+        .find(|t| codecs().get_codec(t.codec_params.codec).is_some())
+        .map(|t| (t.id, t.codec_params.clone()))
+        .ok_or_else(|| {
+            let opus = format
+                .tracks()
+                .iter()
+                .any(|t| t.codec_params.codec == CODEC_TYPE_OPUS);
+            match opus {
+                true => Error::Unsupported("Opus needs a build with --features opus"),
+                false => Error::Unsupported("no supported audio track"),
+            }
+        })
+}
+
+pub fn probe(path: &str) -> Result<AudioInfo, Error> {
+    let mut probed = probe_format(path)?;
+    // tags can sit in front of the stream (ID3v2) or inside it (Vorbis comments)
+    let mut tags = vec![];
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        tags.extend_from_slice(revision.tags());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.extend_from_slice(revision.tags());
+    }
+    let mut format = probed.format;
+    let (track_id, params) = audio_track(format.as_ref())?;
+    let sample_rate = params
+        .sample_rate
+        .ok_or(Error::Unsupported("unknown sample rate"))?;
+    let mut walked = 0;
+    let channels = match params.channels {
+        Some(channels) => channels.count() as u16,
+        None => {
+            // MP4 leaves the channel count to the AAC stream, so decode a packet to see it
+            let mut decoder = codecs().make(&params, &Default::default())?;
+            loop {
+                let packet = format.next_packet()?;
+                if packet.track_id() == track_id {
+                    walked += packet.dur();
+                    break decoder.decode(&packet)?.spec().channels.count() as u16;
+                }
+            }
+        }
+    };
+    let frames = match params.n_frames {
+        Some(frames) => frames,
+        None => {
+            // No frame count in the headers, so walk the packets without decoding them.
+            let mut frames = walked;
+            while let Ok(packet) = format.next_packet() {
+                if packet.track_id() == track_id {
+                    frames += packet.dur();
+                }
+            }
+            frames
+        }
+    };
+    let duration: Duration = match params.time_base {
+        Some(tb) => tb.calc_time(frames).into(),
+        None => Duration::from_secs_f64(frames as f64 / sample_rate as f64),
+    };
+    if duration.is_zero() {
+        return Err(Error::Unsupported("empty audio track"));
+    }
+    let size = std::fs::metadata(path)?.len();
+    let bitrate = (size as f64 * 8.0 / duration.as_secs_f64() / 1000.0).round() as u32;
+    Ok(AudioInfo {
+        duration,
+        sample_rate,
+        channels,
+        bitrate,
+        replay_gain: ReplayGain::from_tags(&tags),
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::media::ts::tests::{adts_silence, ts_segment};
+
+    pub fn bundled_mp3() -> Vec<u8> {
+        fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/music/Chelsea Grin - Dead Rose.mp3"
+        ))
+        .unwrap()
+    }
+
+    /// How many samples the stream decodes to, all channels counted.
+    pub fn decode_all(mut format: Box<dyn FormatReader>) -> usize {
+        let (track_id, params) = audio_track(format.as_ref()).unwrap();
+        let mut decoder = codecs().make(&params, &Default::default()).unwrap();
+        let mut samples = 0;
+        while let Ok(packet) = format.next_packet() {
+            if packet.track_id() != track_id {
+                continue;
+            }
+            let decoded = decoder.decode(&packet).unwrap();
+            samples += decoded.frames() * decoded.spec().channels.count();
+        }
+        samples
+    }
+
+    #[test]
+    fn transport_stream_files_are_probed_through_their_audio() {
+        let path = std::env::temp_dir().join(format!("probe-{}.ts", std::process::id()));
+        fs::write(&path, ts_segment(0x0f, &adts_silence(430))).unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let info = probe(&path);
+        let samples = decode_all(open_format(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let info = info.unwrap();
+        assert_eq!((info.sample_rate, info.channels), (44100, 2));
+        assert_eq!(info.duration.as_millis(), 430 * 1024 * 1000 / 44100);
+        assert_eq!(samples, 430 * 1024 * 2);
+    }
+
+    #[test]
+    fn transport_stream_without_audio_is_refused() {
+        let path = std::env::temp_dir().join(format!("probe-video-{}.ts", std::process::id()));
+        // a program carrying only H.264 video
+        fs::write(&path, ts_segment(0x1b, &[0; 1000])).unwrap();
+
+        let probed = probe(&path.to_string_lossy());
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(probed, Err(Error::Unsupported(_))));
+    }
+
+    /// An Ogg page holding `packet` whole, its checksum filled in.
+    #[cfg(not(feature = "opus"))]
+    fn ogg_page(flags: u8, sequence: u32, granule: u64, packet: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\0".to_vec();
+        page.push(flags);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&1u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        let mut lacing = vec![255; packet.len() / 255];
+        lacing.push((packet.len() % 255) as u8);
+        page.push(lacing.len() as u8);
+        page.extend(lacing);
+        page.extend_from_slice(packet);
+        let crc = page.iter().fold(0u32, |crc, byte| {
+            (0..8).fold(crc ^ ((*byte as u32) << 24), |crc, _| {
+                match crc & 0x8000_0000 {
+                    0 => crc << 1,
+                    _ => (crc << 1) ^ 0x04c1_1db7,
+                }
+            })
+        });
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    }
+
+    #[cfg(not(feature = "opus"))]
+    #[test]
+    fn opus_without_the_feature_says_how_to_get_it() {
+        let path = std::env::temp_dir().join(format!("probe-{}.opus", std::process::id()));
+        let head = b"OpusHead\x01\x02\x38\x01\x80\xbb\0\0\0\0\0";
+        let tags = b"OpusTags\0\0\0\0\0\0\0\0";
+        let mut file = ogg_page(0x02, 0, 0, head);
+        file.extend(ogg_page(0, 1, 0, tags));
+        // one 20 ms frame of stereo silence
+        file.extend(ogg_page(0x04, 2, 960, &[0xfc, 0xff, 0xfe]));
+        fs::write(&path, file).unwrap();
+
+        let probed = probe(&path.to_string_lossy());
+        fs::remove_file(&path).unwrap();
+
+        match probed {
+            Err(Error::Unsupported(msg)) => assert!(msg.contains("--features opus"), "{}", msg),
+            Err(err) => panic!("{}", err),
+            Ok(_) => panic!("opus decoded without the feature"),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Longest a station name or song title gets in a file name.
+const MAX_NAME: usize = 80;
+
+/// Writes a stream's audio to disk as it arrives, as it was sent, starting
+/// a new file whenever the station announces another song.
+pub struct Recorder {
+    dir: PathBuf,
+    station: String,
+    extension: &'static str,
+    title: Option<String>,
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn start(
+        dir: &Path,
+        station: &str,
+        extension: &'static str,
+        title: Option<String>,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let (path, file) = create(dir, station, title.as_deref(), extension)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            station: station.to_string(),
+            extension,
+            title,
+            path,
+            file,
+        })
+    }
+
+    /// The file being written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `data`, first moving on to a new file if `title` is new.
+    pub fn write(&mut self, data: &[u8], title: Option<&str>) -> io::Result<()> {
+        if title.is_some() && title != self.title.as_deref() {
+            self.file.flush()?;
+            let (path, file) = create(&self.dir, &self.station, title, self.extension)?;
+            self.path = path;
+            self.file = file;
+            self.title = title.map(String::from);
+        }
+        self.file.write_all(data)
+    }
+}
+
+/// A file named after the station, the time and the song, if there is one.
+fn create(
+    dir: &Path,
+    station: &str,
+    title: Option<&str>,
+    extension: &str,
+) -> io::Result<(PathBuf, BufWriter<File>)> {
+    let mut name = format!("{} {}", file_name(station), timestamp(SystemTime::now()));
+    if let Some(title) = title {
+        name.push_str(" - ");
+        name.push_str(&file_name(title));
+    }
+    let path = dir.join(format!("{}.{}", name, extension));
+    let file = File::create(&path)?;
+    Ok((path, BufWriter::new(file)))
+}
+
+/// `name` without the characters file systems reject.
+fn file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(MAX_NAME)
+        .collect();
+    name.trim().to_string()
+}
+
+/// The UTC date and time as `2024-05-01 18-30-00`.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // days since 1970 to a civil date, after Howard Hinnant's algorithm
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}-{:02}-{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
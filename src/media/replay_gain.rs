@@ -0,0 +1,369 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use ebur128::{EbuR128, Mode};
+use rodio::Source;
+use symphonia::core::meta::{StandardTagKey, Tag};
+
+use super::track::Track;
+
+/// ReplayGain 2.0 reference loudness in LUFS.
+const REFERENCE_LOUDNESS: f64 = -18.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReplayGainMode {
+    Off,
+    Track,
+    /// Album gain where tagged. Files without album tags play at their track
+    /// gain, tagged or measured, as only single files are measured.
+    Album,
+}
+
+impl ReplayGainMode {
+    pub fn cycle(self) -> Self {
+        match self {
+            ReplayGainMode::Off => ReplayGainMode::Track,
+            ReplayGainMode::Track => ReplayGainMode::Album,
+            ReplayGainMode::Album => ReplayGainMode::Off,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ReplayGainMode::Off => "off",
+            ReplayGainMode::Track => "track",
+            ReplayGainMode::Album => "album (track gain where untagged)",
+        }
+    }
+}
+
+/// ReplayGain values read from a file's tags, gains in dB.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    pub fn from_tags(tags: &[Tag]) -> Self {
+        let mut gain = Self::default();
+        for tag in tags {
+            let value = tag
+                .value
+                .to_string()
+                .trim()
+                .trim_end_matches("dB")
+                .trim()
+                .parse::<f32>()
+                .ok();
+            let key = match tag.std_key {
+                Some(key) => key,
+                None => match tag.key.to_lowercase().trim_start_matches("txxx:") {
+                    "replaygain_track_gain" => StandardTagKey::ReplayGainTrackGain,
+                    "replaygain_track_peak" => StandardTagKey::ReplayGainTrackPeak,
+                    "replaygain_album_gain" => StandardTagKey::ReplayGainAlbumGain,
+                    "replaygain_album_peak" => StandardTagKey::ReplayGainAlbumPeak,
+                    _ => continue,
+                },
+            };
+            match key {
+                StandardTagKey::ReplayGainTrackGain => gain.track_gain = value,
+                StandardTagKey::ReplayGainTrackPeak => gain.track_peak = value,
+                StandardTagKey::ReplayGainAlbumGain => gain.album_gain = value,
+                StandardTagKey::ReplayGainAlbumPeak => gain.album_peak = value,
+                _ => {}
+            }
+        }
+        gain
+    }
+
+    /// The tagged gain and peak `mode` plays at, `None` when it is off.
+    /// Album mode falls back to track values.
+    fn tagged(&self, mode: ReplayGainMode) -> Option<(Option<f32>, Option<f32>)> {
+        match mode {
+            ReplayGainMode::Off => None,
+            ReplayGainMode::Track => Some((self.track_gain, self.track_peak)),
+            ReplayGainMode::Album => Some((
+                self.album_gain.or(self.track_gain),
+                self.album_peak.or(self.track_peak),
+            )),
+        }
+    }
+
+    /// Whether `mode` plays at a measured gain, the tags having none for it.
+    pub fn needs_measuring(&self, mode: ReplayGainMode) -> bool {
+        self.tagged(mode).is_some_and(|(gain, _)| gain.is_none())
+    }
+
+    /// The linear factor to play at for `mode`. Album mode falls back to track
+    /// values, and both fall back to `measured` when the tags are missing, so
+    /// an untagged album plays each file at its own measured loudness.
+    pub fn factor(
+        &self,
+        mode: ReplayGainMode,
+        measured: impl FnOnce() -> Option<(f32, f32)>,
+    ) -> f32 {
+        let (gain, peak) = match self.tagged(mode) {
+            Some(tagged) => tagged,
+            None => return 1.0,
+        };
+        let (gain, peak) = match gain {
+            Some(gain) => (gain, peak),
+            None => match measured() {
+                Some((gain, peak)) => (gain, Some(peak)),
+                None => return 1.0,
+            },
+        };
+        let factor = 10f32.powf(gain / 20.0);
+        match peak.filter(|peak| *peak > 0.0) {
+            // never push the loudest sample past full scale
+            Some(peak) => factor.min(1.0 / peak),
+            None => factor,
+        }
+    }
+}
+
+/// EBU R128 measurements for files without ReplayGain tags, kept in memory
+/// and in `loudness.cache` next to `radio.ini`. Files are measured one at a
+/// time on a background thread, which runs while there are any queued.
+pub struct LoudnessCache {
+    measured: Mutex<HashMap<String, (f32, f32)>>,
+    queue: Mutex<Queue>,
+    file: Option<PathBuf>,
+}
+
+#[derive(Default)]
+struct Queue {
+    /// Files waiting to be measured, the next one first.
+    waiting: VecDeque<String>,
+    /// Every file ever queued, so none is measured twice, nor retried
+    /// when it can't be read.
+    pending: HashSet<String>,
+    running: bool,
+}
+
+impl LoudnessCache {
+    pub fn load() -> Arc<Self> {
+        let file = dirs::config_dir().map(|mut dir| {
+            dir.push("Wy-Media");
+            dir.push("loudness.cache");
+            dir
+        });
+        let mut measured = HashMap::new();
+        if let Some(f) = file.as_ref().and_then(|file| File::open(file).ok()) {
+            for line in BufReader::new(f).lines().map_while(Result::ok) {
+                let mut fields = line.splitn(3, ' ');
+                if let (Some(gain), Some(peak), Some(path)) =
+                    (fields.next(), fields.next(), fields.next())
+                {
+                    if let (Ok(gain), Ok(peak)) = (gain.parse(), peak.parse()) {
+                        measured.insert(path.to_string(), (gain, peak));
+                    }
+                }
+            }
+        }
+        Arc::new(Self {
+            measured: Mutex::new(measured),
+            queue: Mutex::new(Queue::default()),
+            file,
+        })
+    }
+
+    /// Returns the measured gain and peak for `path`. A file not measured
+    /// yet is measured next, as it is about to play.
+    pub fn get(self: &Arc<Self>, path: &str) -> Option<(f32, f32)> {
+        let path = key(path);
+        if let Some(measured) = self.measured.lock().unwrap().get(&path) {
+            return Some(*measured);
+        }
+        self.enqueue(path, true);
+        None
+    }
+
+    /// Queues `path` to be measured in the background, so its gain is known
+    /// by the time it plays.
+    pub fn measure_later(self: &Arc<Self>, path: &str) {
+        let path = key(path);
+        if !self.measured.lock().unwrap().contains_key(&path) {
+            self.enqueue(path, false);
+        }
+    }
+
+    fn enqueue(self: &Arc<Self>, path: String, first: bool) {
+        let mut queue = self.queue.lock().unwrap();
+        if !queue.pending.insert(path.clone()) {
+            // already waiting, unless it is being measured now
+            if !first {
+                return;
+            }
+            match queue.waiting.iter().position(|waiting| *waiting == path) {
+                Some(at) => queue.waiting.remove(at),
+                None => return,
+            };
+        }
+        match first {
+            true => queue.waiting.push_front(path),
+            false => queue.waiting.push_back(path),
+        }
+        if !queue.running {
+            queue.running = true;
+            let cache = self.clone();
+            thread::spawn(move || cache.work());
+        }
+    }
+
+    /// Measures queued files until there are none left.
+    fn work(&self) {
+        loop {
+            let path = {
+                let mut queue = self.queue.lock().unwrap();
+                match queue.waiting.pop_front() {
+                    Some(path) => path,
+                    None => {
+                        queue.running = false;
+                        return;
+                    }
+                }
+            };
+            if let Some(measured) = measure(&path) {
+                self.insert(path, measured);
+            }
+        }
+    }
+
+    fn insert(&self, path: String, (gain, peak): (f32, f32)) {
+        if let Some(file) = &self.file {
+            if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(file) {
+                let _ = writeln!(f, "{} {} {}", gain, peak, path);
+            }
+        }
+        self.measured.lock().unwrap().insert(path, (gain, peak));
+    }
+}
+
+/// The cache key for `path`, the same however the file was reached.
+fn key(path: &str) -> String {
+    match fs::canonicalize(path) {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// Decodes the whole file and returns its ReplayGain in dB and sample peak.
+fn measure(path: &str) -> Option<(f32, f32)> {
+    let (mut track, _) = Track::open(path).ok()?;
+    let channels = track.channels() as u32;
+    let mut meter =
+        EbuR128::new(channels, track.sample_rate(), Mode::I | Mode::SAMPLE_PEAK).ok()?;
+    let mut chunk = Vec::with_capacity(4096 * channels as usize);
+    loop {
+        chunk.clear();
+        chunk.extend(track.by_ref().take(4096 * channels as usize));
+        if chunk.is_empty() {
+            break;
+        }
+        let frames = chunk.len() - chunk.len() % channels as usize;
+        meter.add_frames_f32(&chunk[..frames]).ok()?;
+    }
+    let loudness = meter.loudness_global().ok()?;
+    if !loudness.is_finite() {
+        return None;
+    }
+    let peak = (0..channels)
+        .filter_map(|channel| meter.sample_peak(channel).ok())
+        .fold(0.0, f64::max);
+    Some(((REFERENCE_LOUDNESS - loudness) as f32, peak as f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn untagged_cache() -> Arc<LoudnessCache> {
+        // no file, so tests leave the user's cache alone
+        Arc::new(LoudnessCache {
+            measured: Mutex::new(HashMap::new()),
+            queue: Mutex::new(Queue::default()),
+            file: None,
+        })
+    }
+
+    #[test]
+    fn only_missing_gains_are_measured() {
+        let album = ReplayGain {
+            album_gain: Some(-3.0),
+            ..Default::default()
+        };
+        assert!(!album.needs_measuring(ReplayGainMode::Off));
+        assert!(album.needs_measuring(ReplayGainMode::Track));
+        assert!(!album.needs_measuring(ReplayGainMode::Album));
+        let track = ReplayGain {
+            track_gain: Some(-3.0),
+            ..Default::default()
+        };
+        // album mode falls back to the track's gain
+        assert!(!track.needs_measuring(ReplayGainMode::Album));
+    }
+
+    #[test]
+    fn factor_keeps_peaks_under_full_scale() {
+        let gain = ReplayGain {
+            track_gain: Some(6.0),
+            track_peak: Some(0.8),
+            ..Default::default()
+        };
+        assert_eq!(gain.factor(ReplayGainMode::Off, || None), 1.0);
+        assert_eq!(gain.factor(ReplayGainMode::Track, || None), 1.25);
+        let measured = ReplayGain::default().factor(ReplayGainMode::Album, || Some((-6.0, 0.9)));
+        assert!((measured - 0.501).abs() < 1e-3);
+    }
+
+    #[test]
+    fn queued_files_are_measured_before_they_are_asked_for() {
+        let cache = untagged_cache();
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/music/Chelsea Grin - Dead Rose.mp3"
+        );
+
+        cache.measure_later(path);
+        let until = Instant::now() + Duration::from_secs(120);
+        while !cache.measured.lock().unwrap().contains_key(&key(path)) {
+            assert!(Instant::now() < until, "not measured in time");
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        let (gain, peak) = cache.get(path).unwrap();
+        assert!(gain.is_finite() && gain.abs() < 30.0);
+        assert!(peak > 0.0 && peak <= 1.5);
+        assert!(!cache.queue.lock().unwrap().running);
+    }
+
+    #[test]
+    fn asked_for_file_jumps_the_queue() {
+        let cache = untagged_cache();
+        {
+            // hold the worker off while the queue is filled
+            let mut queue = cache.queue.lock().unwrap();
+            queue.running = true;
+        }
+        cache.measure_later("a.mp3");
+        cache.measure_later("b.mp3");
+        cache.measure_later("a.mp3");
+        assert_eq!(cache.get("b.mp3"), None);
+        cache.get("c.mp3");
+
+        let queue = cache.queue.lock().unwrap();
+        assert_eq!(queue.waiting, ["c.mp3", "b.mp3", "a.mp3"]);
+    }
+}
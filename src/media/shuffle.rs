@@ -0,0 +1,109 @@
+use rand::{seq::SliceRandom, Rng};
+
+/// A shuffled play order over playlist indices. Every track is played once
+/// before the order is reshuffled for the next round.
+pub struct ShuffleOrder {
+    order: Vec<usize>,
+    played: usize,
+    history: Vec<usize>,
+    forward: Vec<usize>,
+}
+
+impl ShuffleOrder {
+    pub fn new(len: usize, current: Option<usize>) -> Self {
+        let mut shuffle = Self {
+            order: vec![],
+            played: 0,
+            history: vec![],
+            forward: vec![],
+        };
+        shuffle.reshuffle(len, None);
+        if let Some(current) = current {
+            shuffle.select(current);
+        }
+        shuffle
+    }
+
+    fn reshuffle(&mut self, len: usize, last: Option<usize>) {
+        let mut rng = rand::thread_rng();
+        self.order = (0..len).collect();
+        self.order.shuffle(&mut rng);
+        // don't start the new round with the track that ended the last one
+        if len > 1 && last == Some(self.order[0]) {
+            let swap = rng.gen_range(1..len);
+            self.order.swap(0, swap);
+        }
+        self.played = 0;
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.forward.is_empty() && self.played >= self.order.len()
+    }
+
+    /// The track to play after `current`, starting a new round when this one is used up.
+    pub fn peek_next(&mut self, current: usize) -> Option<usize> {
+        if let Some(&index) = self.forward.last() {
+            return Some(index);
+        }
+        if self.is_exhausted() {
+            self.reshuffle(self.order.len(), Some(current));
+        }
+        self.order.get(self.played).copied()
+    }
+
+    /// Steps back through the tracks played so far, returning the one before the current.
+    pub fn back(&mut self) -> Option<usize> {
+        if self.history.len() < 2 {
+            return None;
+        }
+        let current = self.history.pop().unwrap();
+        self.forward.push(current);
+        self.history.last().copied()
+    }
+
+    /// Marks `index` as played in this round.
+    pub fn select(&mut self, index: usize) {
+        if self.history.last() == Some(&index) {
+            return;
+        }
+        if self.forward.last() == Some(&index) {
+            self.forward.pop();
+        } else {
+            self.forward.clear();
+        }
+        self.history.push(index);
+        if let Some(pos) = self.order.iter().position(|&i| i == index) {
+            if pos >= self.played {
+                self.order.swap(pos, self.played);
+                self.played += 1;
+            }
+        }
+    }
+
+    /// Slots a newly appended playlist index somewhere in the unplayed part.
+    pub fn insert(&mut self, index: usize) {
+        let pos = rand::thread_rng().gen_range(self.played..=self.order.len());
+        self.order.insert(pos, index);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if let Some(pos) = self.order.iter().position(|&i| i == index) {
+            self.order.remove(pos);
+            if pos < self.played {
+                self.played -= 1;
+            }
+        }
+        self.history.retain(|&i| i != index);
+        self.forward.retain(|&i| i != index);
+        for i in self
+            .order
+            .iter_mut()
+            .chain(self.history.iter_mut())
+            .chain(self.forward.iter_mut())
+        {
+            if *i > index {
+                *i -= 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use rodio::Source;
+
+/// How many of the most recent mono samples are kept for analysis.
+pub const WINDOW: usize = 2048;
+
+/// Mono samples are handed over in blocks of this many frames to keep locking rare.
+const BLOCK: usize = 256;
+
+/// The most recent audio heard, fed by the `SpectrumTap` of the track that
+/// started last so a fading-out track doesn't mix into the picture.
+#[derive(Default)]
+pub struct Spectrum {
+    samples: Mutex<VecDeque<f32>>,
+    sample_rate: AtomicU32,
+    current: AtomicU64,
+    next_id: AtomicU64,
+}
+
+impl Spectrum {
+    /// The last `WINDOW` mono samples and their sample rate.
+    pub fn snapshot(&self) -> (Vec<f32>, u32) {
+        let samples = self.samples.lock().unwrap();
+        (
+            samples.iter().copied().collect(),
+            self.sample_rate.load(Ordering::Relaxed),
+        )
+    }
+
+    fn push(&self, id: u64, block: &[f32], sample_rate: u32) {
+        if self.current.load(Ordering::Relaxed) != id {
+            return;
+        }
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        let mut samples = self.samples.lock().unwrap();
+        samples.extend(block);
+        let excess = samples.len().saturating_sub(WINDOW);
+        samples.drain(..excess);
+    }
+}
+
+/// Passes samples through untouched while copying a mono mix to a `Spectrum`.
+pub struct SpectrumTap<S> {
+    inner: S,
+    spectrum: Arc<Spectrum>,
+    id: u64,
+    channels: usize,
+    channel: usize,
+    frame: f32,
+    block: Vec<f32>,
+    started: bool,
+}
+
+impl<S> SpectrumTap<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, spectrum: Arc<Spectrum>) -> Self {
+        let id = spectrum.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        Self {
+            channels: inner.channels().max(1) as usize,
+            inner,
+            spectrum,
+            id,
+            channel: 0,
+            frame: 0.0,
+            block: Vec::with_capacity(BLOCK),
+            started: false,
+        }
+    }
+}
+
+impl<S> Iterator for SpectrumTap<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        if !self.started {
+            self.started = true;
+            self.spectrum.current.store(self.id, Ordering::Relaxed);
+        }
+        self.frame += sample;
+        self.channel += 1;
+        if self.channel == self.channels {
+            self.block.push(self.frame / self.channels as f32);
+            self.channel = 0;
+            self.frame = 0.0;
+            if self.block.len() >= BLOCK {
+                self.spectrum
+                    .push(self.id, &self.block, self.inner.sample_rate());
+                self.block.clear();
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<S> Source for SpectrumTap<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Mutex},
+    time::Duration,
+};
+
+use symphonia::core::{
+    errors::Error,
+    formats::{FormatOptions, FormatReader},
+    io::{MediaSource, MediaSourceStream},
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+use super::record::Recorder;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An HTTP client that gives up on a station that stops sending.
+pub fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build()
+}
+
+/// Shared between the player and the thread reading a stream: the song the
+/// station says is playing, and the recording of the stream if one was asked for.
+pub struct StreamTap {
+    title: Mutex<Option<String>>,
+    /// The extension a recording is saved with, for the stream's format.
+    extension: Mutex<&'static str>,
+    recorder: Mutex<Option<Recorder>>,
+}
+
+impl StreamTap {
+    pub fn new() -> Self {
+        Self {
+            title: Mutex::new(None),
+            extension: Mutex::new("bin"),
+            recorder: Mutex::new(None),
+        }
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.title.lock().unwrap().clone()
+    }
+
+    pub fn set_title(&self, title: Option<String>) {
+        *self.title.lock().unwrap() = title;
+    }
+
+    pub fn set_extension(&self, extension: &'static str) {
+        *self.extension.lock().unwrap() = extension;
+    }
+
+    /// Starts writing the stream to files in `dir` named after `station`,
+    /// returning the first one.
+    pub fn start_recording(&self, dir: &Path, station: &str) -> io::Result<PathBuf> {
+        let extension = *self.extension.lock().unwrap();
+        let recorder = Recorder::start(dir, station, extension, self.title())?;
+        let path = recorder.path().to_path_buf();
+        *self.recorder.lock().unwrap() = Some(recorder);
+        Ok(path)
+    }
+
+    pub fn stop_recording(&self) {
+        *self.recorder.lock().unwrap() = None;
+    }
+
+    /// The file being recorded to.
+    pub fn recording(&self) -> Option<PathBuf> {
+        let recorder = self.recorder.lock().unwrap();
+        recorder
+            .as_ref()
+            .map(|recorder| recorder.path().to_path_buf())
+    }
+
+    /// Hands audio read from the stream to the recording, if there is one.
+    /// A recording that can't be written to is stopped.
+    pub fn write(&self, data: &[u8]) {
+        let title = self.title();
+        let mut recorder = self.recorder.lock().unwrap();
+        if let Some(rec) = recorder.as_mut() {
+            if rec.write(data, title.as_deref()).is_err() {
+                *recorder = None;
+            }
+        }
+    }
+}
+
+pub fn invalid<E: ToString>(err: E) -> Error {
+    Error::IoError(io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+}
+
+/// Byte chunks fetched on another thread, read one after another as a
+/// stream for symphonia. The stream ends when the sender is dropped.
+pub struct Chunks {
+    receiver: Mutex<Receiver<io::Result<Vec<u8>>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Chunks {
+    /// Reads `first`, then whatever arrives on `receiver`.
+    pub fn new(receiver: Receiver<io::Result<Vec<u8>>>, first: Vec<u8>) -> Self {
+        Self {
+            receiver: Mutex::new(receiver),
+            chunk: first,
+            pos: 0,
+        }
+    }
+
+    /// Probes the stream for its container, which `extension` hints at.
+    pub fn into_format(self, extension: Option<&str>) -> Result<Box<dyn FormatReader>, Error> {
+        let mut hint = Hint::new();
+        if let Some(extension) = extension {
+            hint.with_extension(extension);
+        }
+        let mss = MediaSourceStream::new(Box::new(self), Default::default());
+        let probed = symphonia::default::get_probe().format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        Ok(probed.format)
+    }
+}
+
+impl Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.chunk.len() {
+            match self.receiver.get_mut().unwrap().recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                // the stream ended
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl Seek for Chunks {
+    fn seek(&mut self, _: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "streams can't seek",
+        ))
+    }
+}
+
+impl MediaSource for Chunks {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::Arc,
+        thread,
+    };
+
+    use super::*;
+
+    /// A file the test server has, with the headers it is sent with.
+    struct Served {
+        headers: Vec<(String, String)>,
+        data: Vec<u8>,
+    }
+
+    /// A request the test server got, header names in lower case.
+    struct Request {
+        path: String,
+        headers: HashMap<String, String>,
+    }
+
+    /// Serves files over HTTP on a local port, noting every request. The
+    /// files can be changed while it runs, as a live playlist would be.
+    pub struct Server {
+        pub url: String,
+        files: Arc<Mutex<HashMap<String, Served>>>,
+        requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    impl Server {
+        pub fn start(files: &[(&str, Vec<u8>)]) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let server = Self {
+                url: format!("http://{}/", listener.local_addr().unwrap()),
+                files: Arc::new(Mutex::new(HashMap::new())),
+                requests: Arc::new(Mutex::new(vec![])),
+            };
+            for (path, data) in files {
+                server.set(path, data.clone());
+            }
+            let (served, asked) = (server.files.clone(), server.requests.clone());
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    let request = read_request(&stream);
+                    let response = match served.lock().unwrap().get(&request.path) {
+                        Some(file) => {
+                            let mut head = "HTTP/1.0 200 OK\r\n".to_string();
+                            for (name, value) in &file.headers {
+                                head.push_str(&format!("{}: {}\r\n", name, value));
+                            }
+                            head.push_str(&format!("Content-Length: {}\r\n\r\n", file.data.len()));
+                            let mut response = head.into_bytes();
+                            response.extend_from_slice(&file.data);
+                            response
+                        }
+                        None => b"HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                    };
+                    asked.lock().unwrap().push(request);
+                    let _ = stream.write_all(&response);
+                }
+            });
+            server
+        }
+
+        pub fn set(&self, path: &str, data: impl Into<Vec<u8>>) {
+            self.set_with_headers(path, &[], data);
+        }
+
+        /// Serves `data` at `path` with the response headers given.
+        pub fn set_with_headers(
+            &self,
+            path: &str,
+            headers: &[(&str, &str)],
+            data: impl Into<Vec<u8>>,
+        ) {
+            let file = Served {
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                data: data.into(),
+            };
+            self.files.lock().unwrap().insert(path.to_string(), file);
+        }
+
+        /// The paths asked for that start with `prefix`, in order.
+        pub fn requested(&self, prefix: &str) -> Vec<String> {
+            let requests = self.requests.lock().unwrap();
+            requests
+                .iter()
+                .filter(|request| request.path.starts_with(prefix))
+                .map(|request| request.path.clone())
+                .collect()
+        }
+
+        /// The header `name` of the first request for `path`.
+        pub fn request_header(&self, path: &str, name: &str) -> Option<String> {
+            let requests = self.requests.lock().unwrap();
+            let request = requests.iter().find(|request| request.path == path)?;
+            request.headers.get(&name.to_lowercase()).cloned()
+        }
+    }
+
+    fn read_request(stream: &std::net::TcpStream) -> Request {
+        let mut lines = BufReader::new(stream).lines().map_while(Result::ok);
+        let first = lines.next().unwrap_or_default();
+        let path = first.split(' ').nth(1).unwrap_or("/")[1..].to_string();
+        let headers = lines
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some((name.trim().to_lowercase(), value.trim().to_string()))
+            })
+            .collect();
+        Request { path, headers }
+    }
+}
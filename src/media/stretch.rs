@@ -0,0 +1,261 @@
+use std::{
+    f32::consts::PI,
+    sync::atomic::{AtomicU32, Ordering},
+    sync::Arc,
+    time::Duration,
+};
+
+use rodio::Source;
+
+use super::track::TrackControl;
+
+/// Playback rate shared between the player and every `TimeStretch` in the sink.
+pub struct PlaybackRate(AtomicU32);
+
+impl PlaybackRate {
+    pub fn new(rate: f32) -> Self {
+        Self(AtomicU32::new(rate.to_bits()))
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, rate: f32) {
+        self.0.store(rate.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Changes tempo without changing pitch using WSOLA: windowed frames are read
+/// from the input at `rate` times the output hop and overlap-added, each one
+/// shifted within a small tolerance to line up with the waveform of the last.
+/// It starts afresh whenever the track's control seeks.
+pub struct TimeStretch<S> {
+    inner: S,
+    rate: Arc<PlaybackRate>,
+    control: Arc<TrackControl>,
+    /// The control's seek count the buffered audio is from.
+    seeks: u64,
+    channels: usize,
+    sample_rate: u32,
+    window: Vec<f32>,
+    hop: usize,
+    tolerance: usize,
+    input: Vec<f32>,
+    input_end: Option<usize>,
+    analysis_pos: f64,
+    continuation: Option<usize>,
+    overlap: Vec<f32>,
+    ready: Vec<f32>,
+    ready_pos: usize,
+    finished: bool,
+}
+
+impl<S> TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, rate: Arc<PlaybackRate>, control: Arc<TrackControl>) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let sample_rate = inner.sample_rate();
+        // 40ms frames with 50% overlap, searched within +-10ms
+        let len = ((sample_rate as usize / 25) & !1).max(64);
+        let hop = len / 2;
+        let window = (0..len)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / len as f32).cos())
+            .collect();
+        Self {
+            inner,
+            rate,
+            seeks: control.seeks(),
+            control,
+            channels,
+            sample_rate,
+            window,
+            hop,
+            tolerance: len / 4,
+            input: vec![],
+            input_end: None,
+            analysis_pos: 0.0,
+            continuation: None,
+            overlap: vec![0.0; len * channels],
+            ready: vec![],
+            ready_pos: 0,
+            finished: false,
+        }
+    }
+
+    /// Drops the input and output held, so nothing from before a seek plays.
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.input_end = None;
+        self.analysis_pos = 0.0;
+        self.continuation = None;
+        self.overlap.fill(0.0);
+        self.ready.clear();
+        self.ready_pos = 0;
+        self.finished = false;
+    }
+
+    fn frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    fn fill(&mut self, frames: usize) {
+        while self.input_end.is_none() && self.frames() < frames {
+            match self.inner.next() {
+                Some(sample) => self.input.push(sample),
+                None => {
+                    let end = self.input.len().div_ceil(self.channels);
+                    self.input.resize(end * self.channels, 0.0);
+                    self.input_end = Some(end);
+                }
+            }
+        }
+        if self.frames() < frames {
+            self.input.resize(frames * self.channels, 0.0);
+        }
+    }
+
+    fn mono(&self, frame: usize) -> f32 {
+        let start = frame * self.channels;
+        self.input[start..start + self.channels].iter().sum()
+    }
+
+    /// Finds the frame near `nominal` whose start best continues the audio at `target`.
+    fn search(&self, target: usize, nominal: usize, tolerance: usize) -> usize {
+        let mut best = nominal;
+        let mut best_score = f32::MIN;
+        let mut candidate = nominal.saturating_sub(tolerance);
+        while candidate <= nominal + tolerance {
+            let (mut corr, mut energy) = (0.0, 0.0);
+            for i in (0..self.hop).step_by(4) {
+                let sample = self.mono(candidate + i);
+                corr += self.mono(target + i) * sample;
+                energy += sample * sample;
+            }
+            let score = corr / (energy.sqrt() + 1e-6);
+            if score > best_score {
+                best_score = score;
+                best = candidate;
+            }
+            candidate += 2;
+        }
+        best
+    }
+
+    /// Overlap-adds the next analysis frame and moves a hop of finished output
+    /// into `ready`. Returns false once the input is used up.
+    fn step(&mut self) -> bool {
+        let rate = self.rate.get() as f64;
+        let (len, hop, channels) = (self.window.len(), self.hop, self.channels);
+        let tolerance = if rate == 1.0 { 0 } else { self.tolerance };
+        let nominal = self.analysis_pos.round() as usize;
+        if let Some(end) = self.input_end.filter(|&end| nominal >= end) {
+            // flush what is left of the last frame's fade-out, up to the end of the input
+            let valid = match self.continuation.take() {
+                Some(target) => end.saturating_sub(target).min(hop),
+                None => 0,
+            };
+            self.ready.clear();
+            self.ready
+                .extend_from_slice(&self.overlap[..valid * channels]);
+            self.ready_pos = 0;
+            return valid > 0;
+        }
+        let need = (nominal + tolerance + len).max(self.continuation.map_or(0, |c| c + hop));
+        self.fill(need);
+
+        let pos = match self.continuation {
+            Some(target) if tolerance > 0 => self.search(target, nominal, tolerance),
+            Some(_) => nominal,
+            None => {
+                // pretend a previous frame faded out over the first hop so the
+                // output starts at full level
+                for i in 0..hop * channels {
+                    self.overlap[i] =
+                        self.input[nominal * channels + i] * self.window[hop + i / channels];
+                }
+                nominal
+            }
+        };
+        for i in 0..len * channels {
+            self.overlap[i] += self.input[pos * channels + i] * self.window[i / channels];
+        }
+        let valid = self
+            .input_end
+            .map_or(hop, |end| end.saturating_sub(pos).min(hop));
+        self.ready.clear();
+        self.ready
+            .extend_from_slice(&self.overlap[..valid * channels]);
+        self.ready_pos = 0;
+        self.overlap.copy_within(hop * channels.., 0);
+        let tail = self.overlap.len() - hop * channels;
+        self.overlap[tail..].fill(0.0);
+
+        let continuation = pos + hop;
+        self.continuation = Some(continuation);
+        self.analysis_pos += hop as f64 * rate;
+        if valid < hop {
+            // this frame already reached the end of the input
+            self.continuation = None;
+            return true;
+        }
+
+        // drop input that no later frame or search can reach
+        let keep_from =
+            continuation.min((self.analysis_pos.round() as usize).saturating_sub(self.tolerance));
+        if keep_from > 4 * len {
+            self.input.drain(..keep_from * channels);
+            self.continuation = Some(continuation - keep_from);
+            self.analysis_pos -= keep_from as f64;
+            self.input_end = self.input_end.map(|end| end.saturating_sub(keep_from));
+        }
+        true
+    }
+}
+
+impl<S> Iterator for TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let seeks = self.control.seeks();
+        if seeks != self.seeks {
+            self.seeks = seeks;
+            self.reset();
+        }
+        while self.ready_pos >= self.ready.len() {
+            if self.finished || !self.step() {
+                self.finished = true;
+                return None;
+            }
+        }
+        let sample = self.ready[self.ready_pos];
+        self.ready_pos += 1;
+        Some(sample)
+    }
+}
+
+impl<S> Source for TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+use symphonia::core::audio::SignalSpec;
+
+/// How much of a stream is kept, played and unplayed together. Samples are
+/// kept as 16 bits, so five minutes of 48 kHz stereo take about 55 MB.
+const KEPT: Duration = Duration::from_secs(5 * 60);
+
+/// The decoded audio of a live stream, kept so it can be paused, rewound and
+/// caught up again. The decoding thread adds audio as it arrives and the
+/// track reads it from wherever it was seeked to. Once full the oldest played
+/// audio makes room; if none has been played, adding waits for the reader.
+pub struct TimeShift {
+    buffer: Mutex<Buffer>,
+    room: Condvar,
+}
+
+#[derive(Default)]
+struct Buffer {
+    chunks: VecDeque<Chunk>,
+    /// The chunk read next, `chunks.len()` when the reader is at the live edge.
+    read: usize,
+    /// Stream time at the end of the newest chunk.
+    live: Duration,
+    ended: bool,
+    closed: bool,
+}
+
+/// A decoded packet, placed on the stream's clock.
+struct Chunk {
+    spec: SignalSpec,
+    start: Duration,
+    samples: Vec<i16>,
+}
+
+/// What reading the next audio from a `TimeShift` gave.
+pub enum Shifted {
+    Audio(SignalSpec, Vec<f32>),
+    /// The reader is at the live edge and waits for more.
+    Empty,
+    /// The stream is over and all of it was read.
+    Ended,
+}
+
+impl Buffer {
+    fn oldest(&self) -> Duration {
+        self.chunks.front().map_or(self.live, |chunk| chunk.start)
+    }
+}
+
+impl TimeShift {
+    pub fn new() -> Self {
+        Self {
+            buffer: Mutex::new(Buffer::default()),
+            room: Condvar::new(),
+        }
+    }
+
+    /// Adds the next packet of the stream, waiting for room if need be.
+    /// Returns false once the reader is gone.
+    pub fn push(&self, spec: SignalSpec, samples: &[f32]) -> bool {
+        let frames = samples.len() / spec.channels.count().max(1);
+        let duration = Duration::from_secs_f64(frames as f64 / spec.rate.max(1) as f64);
+        let mut buffer = self.buffer.lock().unwrap();
+        loop {
+            if buffer.closed {
+                return false;
+            }
+            if buffer.chunks.is_empty() || buffer.live + duration - buffer.oldest() <= KEPT {
+                break;
+            }
+            if buffer.read > 0 {
+                buffer.chunks.pop_front();
+                buffer.read -= 1;
+            } else {
+                buffer = self.room.wait(buffer).unwrap();
+            }
+        }
+        let chunk = Chunk {
+            spec,
+            start: buffer.live,
+            samples: samples
+                .iter()
+                .map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+                .collect(),
+        };
+        buffer.chunks.push_back(chunk);
+        buffer.live += duration;
+        true
+    }
+
+    /// Marks the stream as over, once the decoder has nothing more.
+    pub fn end(&self) {
+        self.buffer.lock().unwrap().ended = true;
+    }
+
+    /// Lets the decoding thread go, as nothing will read any more.
+    pub fn close(&self) {
+        self.buffer.lock().unwrap().closed = true;
+        self.room.notify_all();
+    }
+
+    pub fn read(&self) -> Shifted {
+        let mut buffer = self.buffer.lock().unwrap();
+        let chunk = match buffer.chunks.get(buffer.read) {
+            Some(chunk) => chunk,
+            None if buffer.ended => return Shifted::Ended,
+            None => return Shifted::Empty,
+        };
+        let samples = chunk
+            .samples
+            .iter()
+            .map(|sample| *sample as f32 / i16::MAX as f32)
+            .collect();
+        let spec = chunk.spec;
+        buffer.read += 1;
+        self.room.notify_all();
+        Shifted::Audio(spec, samples)
+    }
+
+    /// Moves the reader to `pos` on the stream's clock, as far as the audio
+    /// kept goes. Anything at or past the live edge catches up with it.
+    pub fn seek(&self, pos: Duration) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.read = if pos >= buffer.live {
+            buffer.chunks.len()
+        } else {
+            buffer
+                .chunks
+                .partition_point(|chunk| chunk.start <= pos)
+                .saturating_sub(1)
+        };
+        self.room.notify_all();
+    }
+
+    /// Where the reader is on the stream's clock.
+    pub fn position(&self) -> Duration {
+        let buffer = self.buffer.lock().unwrap();
+        buffer
+            .chunks
+            .get(buffer.read)
+            .map_or(buffer.live, |chunk| chunk.start)
+    }
+
+    /// The stream's clock at the live edge.
+    pub fn live(&self) -> Duration {
+        self.buffer.lock().unwrap().live
+    }
+
+    /// How far back the reader can go on the stream's clock.
+    pub fn oldest(&self) -> Duration {
+        self.buffer.lock().unwrap().oldest()
+    }
+}
@@ -0,0 +1,630 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use rodio::Source;
+use symphonia::core::{
+    audio::{SampleBuffer, SignalSpec},
+    codecs::{Decoder, DecoderOptions},
+    errors::Error,
+    formats::{FormatReader, SeekMode, SeekTo},
+};
+
+use super::{
+    probe::{audio_track, codecs, open_format},
+    stretch::PlaybackRate,
+    timeshift::{Shifted, TimeShift},
+};
+
+/// How many frames `Played` counts before publishing the new position.
+const POSITION_BLOCK: u64 = 256;
+
+/// Frames of silence played when a stream has nothing decoded yet.
+const UNDERRUN_FRAMES: usize = 1024;
+
+/// How long a change of gain takes to go from silence to full scale, so a
+/// new ReplayGain value glides in instead of jumping.
+const GAIN_RAMP: Duration = Duration::from_millis(200);
+
+/// Shared between the player and the `Track` playing inside the sink.
+#[derive(Default)]
+pub struct TrackControl {
+    gain: AtomicU32,
+    seek_to: Mutex<Option<Duration>>,
+    /// Counts seeks, so the sources in the chain know to drop what they hold.
+    seeks: AtomicU64,
+    /// How the last seek the track took went, until the player looks.
+    seeked: Mutex<Option<Result<(), Error>>>,
+    seek_done: Condvar,
+    position: Mutex<Duration>,
+    started: AtomicBool,
+    finished: AtomicBool,
+    /// A fade-out asked for and not started yet, in microseconds.
+    fade_out: AtomicU64,
+    cancelled: AtomicBool,
+    buffering: AtomicBool,
+}
+
+impl TrackControl {
+    /// Asks the track to seek to `pos` when the sink next reads from it. The
+    /// position moves once it has, see `wait_seek`.
+    pub fn seek(&self, pos: Duration) {
+        *self.seeked.lock().unwrap() = None;
+        *self.seek_to.lock().unwrap() = Some(pos);
+        self.seeks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Waits up to `timeout` for the track to take the last seek and returns
+    /// how it went, `None` if it hasn't yet, as while the sink is paused.
+    pub fn wait_seek(&self, timeout: Duration) -> Option<Result<(), Error>> {
+        let seeked = self.seeked.lock().unwrap();
+        let (mut seeked, _) = self
+            .seek_done
+            .wait_timeout_while(seeked, timeout, |seeked| seeked.is_none())
+            .unwrap();
+        seeked.take()
+    }
+
+    /// How many seeks there have been, which changes on every one.
+    pub fn seeks(&self) -> u64 {
+        self.seeks.load(Ordering::Relaxed)
+    }
+
+    /// How far into the track the output has got, counted from the samples it took.
+    pub fn position(&self) -> Duration {
+        *self.position.lock().unwrap()
+    }
+
+    /// Sets the linear gain applied to every sample, e.g. from ReplayGain.
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Whether the sink has pulled the first sample of this track.
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::Relaxed)
+    }
+
+    /// Whether the sink has pulled the last sample of this track.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// Fades the track out over `duration` of output and ends it once silent.
+    pub fn fade_out(&self, duration: Duration) {
+        let micros = duration.as_micros().clamp(1, u64::MAX as u128) as u64;
+        self.fade_out.store(micros, Ordering::Relaxed);
+    }
+
+    /// Whether a stream ran out of audio and is playing silence until more arrives.
+    pub fn is_buffering(&self) -> bool {
+        self.buffering.load(Ordering::Relaxed)
+    }
+
+    /// Ends the track as soon as the sink reaches it, used to drop a queued track.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn take_seek(&self) -> Option<Duration> {
+        self.seek_to.lock().unwrap().take()
+    }
+
+    fn finish_seek(&self, pos: Duration, seeked: Result<(), Error>) {
+        if seeked.is_ok() {
+            *self.position.lock().unwrap() = pos;
+        }
+        *self.seeked.lock().unwrap() = Some(seeked);
+        self.seek_done.notify_all();
+    }
+}
+
+enum Input {
+    /// Packets are decoded as the sink asks for samples.
+    Decoder {
+        format: Box<dyn FormatReader>,
+        decoder: Box<dyn Decoder>,
+        track_id: u32,
+        buffer: Option<SampleBuffer<f32>>,
+    },
+    /// Packets are decoded ahead on a thread of their own, as reading from
+    /// the network can block for longer than the output can wait.
+    Live(Arc<TimeShift>),
+}
+
+/// A seekable decoder source built on symphonia.
+pub struct Track {
+    input: Input,
+    samples: Vec<f32>,
+    spec: SignalSpec,
+    pos: usize,
+    skip_until: u64,
+    total_duration: Option<Duration>,
+    /// The gain samples are played at, moving towards the control's.
+    gain: Option<f32>,
+    /// The control's seek count when this last took a seek.
+    seeks: u64,
+    control: Arc<TrackControl>,
+}
+
+impl Track {
+    pub fn open(path: &str) -> Result<(Self, Arc<TrackControl>), Error> {
+        Self::from_format(open_format(path)?)
+    }
+
+    /// A track for a stream that is read as it plays, through `shift`, which
+    /// is what seeks it. If the stream falls behind it plays silence until it
+    /// catches up.
+    pub fn live(
+        format: Box<dyn FormatReader>,
+        shift: Arc<TimeShift>,
+    ) -> Result<(Self, Arc<TrackControl>), Error> {
+        let (mut decoding, control) = Self::from_format(format)?;
+        let spec = decoding.spec;
+        let decoded = shift.clone();
+        thread::spawn(move || {
+            while !decoding.samples.is_empty() {
+                // the sink dropped the track
+                if !decoded.push(decoding.spec, &decoding.samples) {
+                    return;
+                }
+                decoding.refill();
+            }
+            decoded.end();
+        });
+        let mut track = Self {
+            input: Input::Live(shift),
+            samples: vec![],
+            spec,
+            pos: 0,
+            skip_until: 0,
+            total_duration: None,
+            gain: None,
+            seeks: 0,
+            control: control.clone(),
+        };
+        track.refill();
+        Ok((track, control))
+    }
+
+    fn from_format(format: Box<dyn FormatReader>) -> Result<(Self, Arc<TrackControl>), Error> {
+        let (track_id, params) = audio_track(format.as_ref())?;
+        let decoder = codecs().make(&params, &DecoderOptions::default())?;
+        let total_duration = match (params.time_base, params.n_frames) {
+            (Some(tb), Some(frames)) => Some(tb.calc_time(frames).into()),
+            _ => None,
+        };
+        let spec = SignalSpec::new(
+            params.sample_rate.unwrap_or(44100),
+            params.channels.unwrap_or_default(),
+        );
+        let control = Arc::new(TrackControl {
+            gain: AtomicU32::new(1f32.to_bits()),
+            ..Default::default()
+        });
+        let mut track = Self {
+            input: Input::Decoder {
+                format,
+                decoder,
+                track_id,
+                buffer: None,
+            },
+            samples: vec![],
+            spec,
+            pos: 0,
+            skip_until: 0,
+            total_duration,
+            gain: None,
+            seeks: 0,
+            control: control.clone(),
+        };
+        track.refill();
+        Ok((track, control))
+    }
+
+    /// The gain for the next sample. The first one plays at the control's
+    /// gain, later changes ramp to it a step a sample.
+    fn next_gain(&mut self) -> f32 {
+        let target = f32::from_bits(self.control.gain.load(Ordering::Relaxed));
+        let gain = match self.gain {
+            Some(gain) => {
+                let samples = GAIN_RAMP.as_secs_f32() * self.spec.rate as f32;
+                let step = 1.0 / (samples * self.spec.channels.count().max(1) as f32);
+                gain + (target - gain).clamp(-step, step)
+            }
+            None => target,
+        };
+        self.gain = Some(gain);
+        gain
+    }
+
+    fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    fn seek(&mut self, pos: Duration) -> Result<(), Error> {
+        let (format, decoder, track_id) = match &mut self.input {
+            Input::Decoder {
+                format,
+                decoder,
+                track_id,
+                ..
+            } => (format, decoder, *track_id),
+            Input::Live(_) => return Ok(()),
+        };
+        let seeked = format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: pos.into(),
+                track_id: Some(track_id),
+            },
+        )?;
+        decoder.reset();
+        self.skip_until = seeked.required_ts;
+        Ok(())
+    }
+
+    /// Decodes the next packet into the buffer, applying any pending seek first.
+    /// Leaves the buffer empty once the stream is exhausted.
+    fn refill(&mut self) {
+        self.seeks = self.control.seeks();
+        if let Some(pos) = self.control.take_seek() {
+            let seeked = self.seek(pos);
+            self.control.finish_seek(pos, seeked);
+        }
+        self.pos = 0;
+        let (format, decoder, track_id, buffer) = match &mut self.input {
+            Input::Decoder {
+                format,
+                decoder,
+                track_id,
+                buffer,
+            } => (format, decoder, *track_id, buffer),
+            Input::Live(shift) => {
+                let read = shift.read();
+                let buffering = matches!(read, Shifted::Empty);
+                self.control.buffering.store(buffering, Ordering::Relaxed);
+                match read {
+                    Shifted::Audio(spec, samples) => {
+                        self.spec = spec;
+                        self.samples = samples;
+                    }
+                    Shifted::Empty => {
+                        let silence = UNDERRUN_FRAMES * self.spec.channels.count();
+                        self.samples.clear();
+                        self.samples.resize(silence, 0.0);
+                    }
+                    Shifted::Ended => self.samples.clear(),
+                }
+                return;
+            }
+        };
+        while let Ok(packet) = format.next_packet() {
+            if packet.track_id() != track_id {
+                continue;
+            }
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(Error::DecodeError(_)) => continue,
+                Err(_) => break,
+            };
+            let frames = decoded.frames() as u64;
+            if frames == 0 || packet.ts() + frames <= self.skip_until {
+                continue;
+            }
+            let spec = *decoded.spec();
+            let channels = spec.channels.count();
+            let reuse = self.spec == spec
+                && buffer
+                    .as_ref()
+                    .is_some_and(|b| b.capacity() >= decoded.capacity() * channels);
+            if !reuse {
+                *buffer = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+                self.spec = spec;
+            }
+            let buffer = buffer.as_mut().unwrap();
+            buffer.copy_interleaved_ref(decoded);
+            self.samples.clear();
+            self.samples.extend_from_slice(buffer.samples());
+            if packet.ts() < self.skip_until {
+                self.pos = (self.skip_until - packet.ts()) as usize * channels;
+            }
+            self.skip_until = 0;
+            return;
+        }
+        self.samples.clear();
+    }
+}
+
+impl Drop for Track {
+    fn drop(&mut self) {
+        if let Input::Live(shift) = &self.input {
+            shift.close();
+        }
+    }
+}
+
+impl Iterator for Track {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.control.cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        if self.control.seeks() != self.seeks {
+            // the rest of the buffer is from before the seek
+            self.refill();
+        }
+        let sample = *self.samples.get(self.pos)? * self.next_gain();
+        self.pos += 1;
+        if self.pos >= self.len() {
+            self.refill();
+        }
+        Some(sample)
+    }
+}
+
+impl Source for Track {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.len() - self.pos)
+    }
+
+    fn channels(&self) -> u16 {
+        self.spec.channels.count() as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.spec.rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+}
+
+/// The outermost source of a track's chain. It advances the track's position
+/// by the frames the output takes, at the playback rate, and marks the track
+/// finished once the output has drained it. Fading out happens here, after
+/// the time stretch, so it lasts as long as the fade-in of the next track.
+pub struct Played<S> {
+    inner: S,
+    control: Arc<TrackControl>,
+    rate: Arc<PlaybackRate>,
+    channels: u64,
+    samples: u64,
+    /// Samples in the fade-out and how many of them are left.
+    fade_out: Option<(u64, u64)>,
+}
+
+impl<S> Played<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, control: Arc<TrackControl>, rate: Arc<PlaybackRate>) -> Self {
+        Self {
+            channels: inner.channels().max(1) as u64,
+            inner,
+            control,
+            rate,
+            samples: 0,
+            fade_out: None,
+        }
+    }
+
+    /// How loud the next sample plays, 1.0 unless fading out, `None` once
+    /// the fade-out is over.
+    fn fade_level(&mut self) -> Option<f32> {
+        let asked = self.control.fade_out.load(Ordering::Relaxed);
+        if asked > 0 {
+            self.control.fade_out.store(0, Ordering::Relaxed);
+            let samples =
+                asked as f64 / 1e6 * self.inner.sample_rate() as f64 * self.channels as f64;
+            self.fade_out = Some(((samples as u64).max(1), samples as u64));
+        }
+        match self.fade_out.as_mut() {
+            Some((_, 0)) => None,
+            Some((total, remaining)) => {
+                *remaining -= 1;
+                Some((*remaining + 1) as f32 / *total as f32)
+            }
+            None => Some(1.0),
+        }
+    }
+
+    fn publish(&mut self) {
+        let frames = self.samples / self.channels;
+        self.samples %= self.channels;
+        let played = Duration::from_secs_f64(
+            frames as f64 / self.inner.sample_rate() as f64 * self.rate.get() as f64,
+        );
+        *self.control.position.lock().unwrap() += played;
+    }
+}
+
+impl<S> Iterator for Played<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let next = match self.fade_level() {
+            Some(level) => self.inner.next().map(|sample| sample * level),
+            None => None,
+        };
+        match next {
+            Some(sample) => {
+                if self.samples == 0 {
+                    self.control.started.store(true, Ordering::Relaxed);
+                }
+                self.samples += 1;
+                if self.samples >= POSITION_BLOCK * self.channels {
+                    self.publish();
+                }
+                Some(sample)
+            }
+            None => {
+                if !self.control.finished.load(Ordering::Relaxed) {
+                    self.publish();
+                    self.control.finished.store(true, Ordering::Relaxed);
+                }
+                None
+            }
+        }
+    }
+}
+
+impl<S> Source for Played<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    use super::*;
+    use crate::media::stretch::TimeStretch;
+
+    /// A second of stereo at a constant level, 1 kHz so the ramp is short.
+    fn constant_wav(name: &str) -> String {
+        wav(name, &[0.5])
+    }
+
+    /// A second of stereo at 1 kHz for each of `levels`, in turn.
+    fn wav(name: &str, levels: &[f32]) -> String {
+        let path = env::temp_dir().join(format!("{}-{}.wav", name, process::id()));
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 1000,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for level in levels {
+            for _ in 0..2000 {
+                writer.write_sample(*level).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn first_samples_play_at_the_gain_set_before() {
+        let path = constant_wav("gain-start");
+        let (mut track, control) = Track::open(&path).unwrap();
+        control.set_gain(0.5);
+
+        let first = track.next();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(first, Some(0.25));
+    }
+
+    #[test]
+    fn gain_changes_ramp_instead_of_stepping() {
+        let path = constant_wav("gain-ramp");
+        let (mut track, control) = Track::open(&path).unwrap();
+        assert_eq!(track.next(), Some(0.5));
+
+        control.set_gain(0.0);
+        let ramp: Vec<f32> = track.by_ref().take(600).collect();
+        fs::remove_file(&path).unwrap();
+
+        // a full scale change takes the ramp's 200 ms, 400 samples at 1 kHz stereo
+        let steps = (GAIN_RAMP.as_secs_f32() * 1000.0 * 2.0) as usize;
+        assert!((ramp[0] - 0.5 * (1.0 - 1.0 / steps as f32)).abs() < 1e-6);
+        assert!(ramp.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(ramp[steps / 2] > 0.2 && ramp[steps / 2] < 0.3);
+        assert!(ramp[steps..].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn fade_out_lasts_as_long_at_any_rate() {
+        for speed in [0.5, 1.0, 2.0] {
+            let path = constant_wav(&format!("fade-{}", speed));
+            let (track, control) = Track::open(&path).unwrap();
+            let rate = Arc::new(PlaybackRate::new(speed));
+            let mut played = Played::new(
+                TimeStretch::new(track, rate.clone(), control.clone()),
+                control.clone(),
+                rate,
+            );
+            played.by_ref().take(100).for_each(drop);
+
+            control.fade_out(Duration::from_millis(100));
+            let fade: Vec<f32> = played.by_ref().collect();
+            fs::remove_file(&path).unwrap();
+
+            // 100 ms of output at 1 kHz stereo, as the next track's fade-in
+            assert_eq!(fade.len(), 200, "at rate {}", speed);
+            assert!(fade.windows(2).all(|pair| pair[1] <= pair[0] + 1e-6));
+            assert!(control.is_finished());
+        }
+    }
+
+    #[test]
+    fn seek_drops_the_audio_the_stretch_holds() {
+        let path = wav("stretch-seek", &[0.25, 0.75]);
+        let (track, control) = Track::open(&path).unwrap();
+        let rate = Arc::new(PlaybackRate::new(1.5));
+        let mut stretched = TimeStretch::new(track, rate, control.clone());
+        assert!(stretched
+            .by_ref()
+            .take(200)
+            .all(|sample| (sample - 0.25).abs() < 1e-3));
+
+        control.seek(Duration::from_millis(1200));
+        let after: Vec<f32> = stretched.by_ref().take(300).collect();
+        fs::remove_file(&path).unwrap();
+
+        assert!(
+            after.iter().all(|sample| (sample - 0.75).abs() < 1e-3),
+            "old audio after the seek: {:?}",
+            &after[..8]
+        );
+    }
+
+    #[test]
+    fn position_moves_only_once_a_seek_succeeds() {
+        let path = wav("seek-result", &[0.25, 0.75]);
+        let (mut track, control) = Track::open(&path).unwrap();
+
+        control.seek(Duration::from_secs(60));
+        assert!(control.wait_seek(Duration::ZERO).is_none());
+        track.next();
+        assert!(matches!(control.wait_seek(Duration::ZERO), Some(Err(_))));
+        assert_eq!(control.position(), Duration::ZERO);
+
+        control.seek(Duration::from_millis(1200));
+        let sample = track.next().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(control.wait_seek(Duration::ZERO), Some(Ok(()))));
+        assert_eq!(control.position(), Duration::from_millis(1200));
+        assert!((sample - 0.75).abs() < 1e-3);
+    }
+}
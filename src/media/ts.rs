@@ -0,0 +1,382 @@
+use std::io::{self, Read};
+
+/// Size of every MPEG transport stream packet.
+pub const PACKET: usize = 188;
+/// Packets read at a time when demuxing a whole file.
+const BATCH: usize = 1024;
+const SYNC: u8 = 0x47;
+
+const PAT_PID: u16 = 0;
+const STREAM_MPEG1_AUDIO: u8 = 0x03;
+const STREAM_MPEG2_AUDIO: u8 = 0x04;
+const STREAM_ADTS_AAC: u8 = 0x0f;
+
+/// The audio codecs a transport stream can carry that there is a reader for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AudioKind {
+    Adts,
+    Mpeg,
+}
+
+impl AudioKind {
+    /// Recognises the frame header raw audio starts with, as in packed audio segments.
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        match data {
+            [0xff, b, ..] if b & 0xf6 == 0xf0 => Some(AudioKind::Adts),
+            [0xff, b, ..] if b & 0xe0 == 0xe0 => Some(AudioKind::Mpeg),
+            _ => None,
+        }
+    }
+
+    /// The file extension symphonia's probe picks the matching reader by.
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioKind::Adts => "aac",
+            AudioKind::Mpeg => "mp3",
+        }
+    }
+}
+
+/// Pulls the first audio stream out of MPEG-TS segments. The program tables
+/// are remembered, so a segment that doesn't repeat them still demuxes.
+#[derive(Default)]
+pub struct TsDemuxer {
+    pmt_pid: Option<u16>,
+    audio: Option<(u16, AudioKind)>,
+}
+
+impl TsDemuxer {
+    /// Whether `data` looks like a transport stream rather than raw audio.
+    pub fn detect(data: &[u8]) -> bool {
+        data.len() >= PACKET && data[0] == SYNC && data.get(PACKET).is_none_or(|b| *b == SYNC)
+    }
+
+    pub fn kind(&self) -> Option<AudioKind> {
+        self.audio.map(|(_, kind)| kind)
+    }
+
+    /// The audio elementary stream carried in `data`, PES headers removed.
+    pub fn demux(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        for packet in data.chunks_exact(PACKET) {
+            if packet[0] != SYNC {
+                continue;
+            }
+            let unit_start = packet[1] & 0x40 != 0;
+            let pid = u16::from_be_bytes([packet[1] & 0x1f, packet[2]]);
+            let payload = match (packet[3] >> 4) & 0x3 {
+                0x1 => &packet[4..],
+                0x3 => match packet.get(5 + packet[4] as usize..) {
+                    Some(payload) => payload,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            if pid == PAT_PID {
+                if let Some(pmt_pid) = section(payload, unit_start).and_then(pat) {
+                    self.pmt_pid = Some(pmt_pid);
+                }
+            } else if Some(pid) == self.pmt_pid {
+                if let Some(audio) = section(payload, unit_start).and_then(pmt) {
+                    self.audio = Some(audio);
+                }
+            } else if Some(pid) == self.audio.map(|(pid, _)| pid) {
+                let data = if unit_start {
+                    match pes_payload(payload) {
+                        Some(data) => data,
+                        None => continue,
+                    }
+                } else {
+                    payload
+                };
+                out.extend_from_slice(data);
+            }
+        }
+        out
+    }
+}
+
+/// Demuxes a whole transport stream, such as a `.ts` file, into its audio
+/// and the codec it is in. `None` when there was no audio stream.
+pub fn demux_all<R: Read>(mut reader: R) -> io::Result<(Vec<u8>, Option<AudioKind>)> {
+    let mut demuxer = TsDemuxer::default();
+    let mut audio = vec![];
+    let mut batch = Vec::with_capacity(BATCH * PACKET);
+    loop {
+        batch.clear();
+        if (&mut reader)
+            .take((BATCH * PACKET) as u64)
+            .read_to_end(&mut batch)?
+            == 0
+        {
+            return Ok((audio, demuxer.kind()));
+        }
+        audio.extend(demuxer.demux(&batch));
+    }
+}
+
+/// The table section starting in this packet. Tables split over packets are
+/// not put back together, PAT and PMT of a single program always fit in one.
+fn section(payload: &[u8], unit_start: bool) -> Option<&[u8]> {
+    if !unit_start {
+        return None;
+    }
+    let pointer = *payload.first()? as usize;
+    let section = payload.get(1 + pointer..)?;
+    let length = (((section.get(1)? & 0x0f) as usize) << 8) | *section.get(2)? as usize;
+    // the length counts from after itself and includes the CRC
+    section.get(..3 + length.checked_sub(4)?)
+}
+
+/// The PMT pid of the first program in a program association table.
+fn pat(section: &[u8]) -> Option<u16> {
+    section
+        .get(8..)?
+        .chunks_exact(4)
+        .find(|program| program[0] != 0 || program[1] != 0)
+        .map(|program| u16::from_be_bytes([program[2] & 0x1f, program[3]]))
+}
+
+/// The first audio stream in a program map table.
+fn pmt(section: &[u8]) -> Option<(u16, AudioKind)> {
+    let info = (((section.get(10)? & 0x0f) as usize) << 8) | *section.get(11)? as usize;
+    let mut streams = section.get(12 + info..)?;
+    while streams.len() >= 5 {
+        let pid = u16::from_be_bytes([streams[1] & 0x1f, streams[2]]);
+        let kind = match streams[0] {
+            STREAM_ADTS_AAC => Some(AudioKind::Adts),
+            STREAM_MPEG1_AUDIO | STREAM_MPEG2_AUDIO => Some(AudioKind::Mpeg),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            return Some((pid, kind));
+        }
+        let es_info = (((streams[3] & 0x0f) as usize) << 8) | streams[4] as usize;
+        streams = streams.get(5 + es_info..)?;
+    }
+    None
+}
+
+fn pes_payload(payload: &[u8]) -> Option<&[u8]> {
+    if payload.get(..3)? != [0, 0, 1] {
+        return None;
+    }
+    let header = *payload.get(8)? as usize;
+    payload.get(9 + header..)
+}
+
+/// Skips the ID3 tags packed audio segments start with.
+pub fn strip_id3(mut data: &[u8]) -> &[u8] {
+    while data.len() >= 10 && &data[..3] == b"ID3" {
+        let size = syncsafe(&data[6..10]);
+        let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+        data = data.get(10 + size + footer..).unwrap_or(&[]);
+    }
+    data
+}
+
+/// The song named in the ID3 tag a packed audio segment starts with, as
+/// "artist - title" when the tag has both.
+pub fn id3_title(data: &[u8]) -> Option<String> {
+    if data.len() < 10 || &data[..3] != b"ID3" {
+        return None;
+    }
+    let version = data[3];
+    // an extended header would need skipping first, radio tags don't have one
+    if data[5] & 0x40 != 0 {
+        return None;
+    }
+    let size = syncsafe(&data[6..10]);
+    let mut frames = data.get(10..10 + size).unwrap_or(&data[10..]);
+    let (mut title, mut artist) = (None, None);
+    while frames.len() >= 10 && frames[0] != 0 {
+        let size = if version >= 4 {
+            syncsafe(&frames[4..8])
+        } else {
+            u32::from_be_bytes([frames[4], frames[5], frames[6], frames[7]]) as usize
+        };
+        let body = frames.get(10..10 + size)?;
+        match &frames[..4] {
+            b"TIT2" => title = id3_text(body),
+            b"TPE1" => artist = id3_text(body),
+            _ => {}
+        }
+        frames = &frames[10 + size..];
+    }
+    match (artist, title) {
+        (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+        (_, title) => title,
+    }
+}
+
+/// The string in an ID3 text frame, in whichever encoding it declares.
+fn id3_text(body: &[u8]) -> Option<String> {
+    let (encoding, text) = body.split_first()?;
+    let text = match encoding {
+        0 => text.iter().map(|b| *b as char).collect(),
+        1 | 2 => {
+            let (big_endian, text) = match text {
+                [0xfe, 0xff, rest @ ..] if *encoding == 1 => (true, rest),
+                [0xff, 0xfe, rest @ ..] if *encoding == 1 => (false, rest),
+                text => (*encoding == 2, text),
+            };
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|unit| match big_endian {
+                    true => u16::from_be_bytes([unit[0], unit[1]]),
+                    false => u16::from_le_bytes([unit[0], unit[1]]),
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(text).into_owned(),
+        _ => return None,
+    };
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// A syncsafe integer, seven bits to a byte.
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0usize, |size, b| (size << 7) | (*b & 0x7f) as usize)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const AUDIO_PID: u16 = 0x101;
+    const PMT_PID: u16 = 0x1000;
+    /// A silent AAC-LC stereo frame, 1024 samples a channel.
+    const AAC_SILENCE: [u8; 9] = [0x21, 0x00, 0x49, 0x90, 0x02, 0x19, 0x00, 0x23, 0x80];
+
+    /// A stream of `frames` silent AAC frames in ADTS.
+    pub fn adts_silence(frames: usize) -> Vec<u8> {
+        let len = 7 + AAC_SILENCE.len();
+        let header = [
+            0xff,
+            0xf1,
+            // AAC-LC at 44.1 kHz
+            (1 << 6) | (4 << 2),
+            // stereo
+            (2 << 6) | (len >> 11) as u8,
+            (len >> 3) as u8,
+            ((len & 7) << 5) as u8 | 0x1f,
+            0xfc,
+        ];
+        let mut data = vec![];
+        for _ in 0..frames {
+            data.extend_from_slice(&header);
+            data.extend_from_slice(&AAC_SILENCE);
+        }
+        data
+    }
+
+    /// A transport stream packet, stuffed with an adaptation field when
+    /// `payload` doesn't fill it.
+    fn packet(pid: u16, unit_start: bool, counter: u8, payload: &[u8], stuff: bool) -> Vec<u8> {
+        let mut packet = vec![
+            0x47,
+            ((unit_start as u8) << 6) | (pid >> 8) as u8,
+            pid as u8,
+            0x10 | (counter & 0x0f),
+        ];
+        if stuff && payload.len() < 184 {
+            let adaptation = 183 - payload.len();
+            packet[3] |= 0x20;
+            packet.push(adaptation as u8);
+            if adaptation > 0 {
+                packet.push(0);
+                packet.resize(5 + adaptation, 0xff);
+            }
+        }
+        packet.extend_from_slice(payload);
+        packet.resize(188, 0xff);
+        packet
+    }
+
+    /// A PSI section behind its pointer field, the CRC left as zeros.
+    fn psi_section(table_id: u8, id: u16, body: &[u8]) -> Vec<u8> {
+        let length = 5 + body.len() + 4;
+        let mut section = vec![
+            0,
+            table_id,
+            0xb0 | (length >> 8) as u8,
+            length as u8,
+            (id >> 8) as u8,
+            id as u8,
+            0xc1,
+            0,
+            0,
+        ];
+        section.extend_from_slice(body);
+        section.extend_from_slice(&[0; 4]);
+        section
+    }
+
+    /// `audio` muxed as the one stream of a single program.
+    pub fn ts_segment(stream_type: u8, audio: &[u8]) -> Vec<u8> {
+        let pat = psi_section(0, 1, &[0, 1, 0xe0 | (PMT_PID >> 8) as u8, PMT_PID as u8]);
+        let pmt = psi_section(
+            2,
+            1,
+            &[
+                0xe0 | (AUDIO_PID >> 8) as u8,
+                AUDIO_PID as u8,
+                0xf0,
+                0,
+                stream_type,
+                0xe0 | (AUDIO_PID >> 8) as u8,
+                AUDIO_PID as u8,
+                0xf0,
+                0,
+            ],
+        );
+        let mut segment = packet(0, true, 0, &pat, false);
+        segment.extend(packet(PMT_PID, true, 0, &pmt, false));
+        let mut pes = vec![0, 0, 1, 0xc0, 0, 0, 0x80, 0, 0];
+        pes.extend_from_slice(audio);
+        for (i, chunk) in pes.chunks(184).enumerate() {
+            segment.extend(packet(AUDIO_PID, i == 0, i as u8, chunk, true));
+        }
+        segment
+    }
+
+    #[test]
+    fn demuxer_keeps_tables_from_earlier_segments() {
+        let mut demuxer = TsDemuxer::default();
+        let audio = adts_silence(3);
+        let segment = ts_segment(0x03, &audio);
+
+        assert!(TsDemuxer::detect(&segment));
+        assert_eq!(demuxer.demux(&segment), audio);
+        assert_eq!(demuxer.kind(), Some(AudioKind::Mpeg));
+        // without its PAT and PMT the next segment still demuxes
+        assert_eq!(demuxer.demux(&segment[2 * 188..]), audio);
+    }
+
+    #[test]
+    fn file_is_demuxed_a_batch_at_a_time() {
+        let audio = adts_silence(15_000);
+        let segment = ts_segment(0x0f, &audio);
+        assert!(segment.len() > BATCH * PACKET);
+
+        let (demuxed, kind) = demux_all(segment.as_slice()).unwrap();
+
+        assert_eq!(demuxed, audio);
+        assert_eq!(kind, Some(AudioKind::Adts));
+    }
+
+    #[test]
+    fn malformed_headers_are_skipped() {
+        assert_eq!(section(&[5, 0], true), None);
+        assert_eq!(section(&[0, 0, 0xb0, 13], false), None);
+        assert_eq!(pes_payload(&[0, 0, 2, 0xc0]), None);
+        assert_eq!(
+            pes_payload(&[0, 0, 1, 0xc0, 0, 0, 0x80, 0x80, 2, 9, 9, 7]),
+            Some(&[7][..])
+        );
+    }
+}
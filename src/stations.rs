@@ -0,0 +1,239 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use crate::ui::radio::RadioConfig;
+
+/// Written at the top of a new `radio.ini`.
+const HEADER: &[&str] = &[
+    "# Wy-Media radio stations, one per line:",
+    "#   [*] name url [genre, genre...]",
+    "# A leading * marks a favourite. Quote a name that has spaces in it,",
+    "# as in \"BBC Radio 3\". Lines starting with # are comments.",
+];
+
+/// The stations in `radio.ini`, with the comments around them so they
+/// survive the file being written back. Comments between stations belong
+/// to the station below them, and go where it goes.
+pub struct StationFile {
+    pub stations: Vec<RadioConfig>,
+    /// Comment lines before the first station.
+    pub header: Vec<String>,
+    /// Comment lines after the last station.
+    pub trailing: Vec<String>,
+}
+
+fn path() -> Option<PathBuf> {
+    let mut config_dir = dirs::config_dir()?;
+    config_dir.push("Wy-Media");
+    config_dir.push("radio.ini");
+    Some(config_dir)
+}
+
+impl StationFile {
+    /// Reads `radio.ini`, skipping the lines that aren't stations. Each one
+    /// skipped is described in the returned warnings.
+    pub fn load() -> (Self, Vec<String>) {
+        let mut file = Self {
+            stations: vec![],
+            header: vec![],
+            trailing: vec![],
+        };
+        let mut warnings = vec![];
+        let f = match path().and_then(|path| File::open(path).ok()) {
+            Some(f) => f,
+            None => {
+                file.header = HEADER.iter().map(|line| line.to_string()).collect();
+                return (file, warnings);
+            }
+        };
+        for (number, line) in BufReader::new(f).lines().map_while(Result::ok).enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                file.trailing.push(line);
+                continue;
+            }
+            match parse_station(trimmed) {
+                Ok(mut station) => {
+                    let comments = std::mem::take(&mut file.trailing);
+                    if file.stations.is_empty() {
+                        file.header = comments;
+                    } else {
+                        station.comments = comments;
+                    }
+                    file.stations.push(station);
+                }
+                Err(err) => {
+                    warnings.push(format!("radio.ini line {}: {}", number + 1, err));
+                    // kept as a comment, so saving doesn't lose it
+                    file.trailing.push(format!("# {}", line));
+                }
+            }
+        }
+        if file.stations.is_empty() {
+            file.header = std::mem::take(&mut file.trailing);
+        }
+        (file, warnings)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = match path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut f = File::create(path)?;
+        for comment in &self.header {
+            writeln!(f, "{}", comment)?;
+        }
+        for station in &self.stations {
+            for comment in &station.comments {
+                writeln!(f, "{}", comment)?;
+            }
+            writeln!(f, "{}", format_station(station))?;
+        }
+        for comment in &self.trailing {
+            writeln!(f, "{}", comment)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_station(line: &str) -> Result<RadioConfig, &'static str> {
+    let (favourite, rest) = match line.strip_prefix('*') {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, line),
+    };
+    let (name, rest) = match rest.strip_prefix('"') {
+        Some(quoted) => unquote(quoted)?,
+        None => split_word(rest),
+    };
+    let (url, rest) = split_word(rest.trim_start());
+    if name.is_empty() {
+        return Err("no station name");
+    }
+    if url.is_empty() {
+        return Err("no url after the station name");
+    }
+    // genres can have spaces in them, as "Classic Rock" does
+    let genres = rest
+        .split(',')
+        .map(str::trim)
+        .filter(|genre| !genre.is_empty())
+        .map(String::from)
+        .collect();
+    Ok(RadioConfig {
+        name,
+        url,
+        genres,
+        favourite,
+        comments: vec![],
+    })
+}
+
+/// The name in a quoted string whose opening quote is already gone, and
+/// what follows the closing one. `\"` and `\\` stand for `"` and `\`.
+fn unquote(quoted: &str) -> Result<(String, &str), &'static str> {
+    let mut name = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((name, &quoted[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, escaped)) => name.push(escaped),
+                None => break,
+            },
+            c => name.push(c),
+        }
+    }
+    Err("the station name's quote isn't closed")
+}
+
+fn split_word(s: &str) -> (String, &str) {
+    match s.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word.to_string(), rest),
+        None => (s.to_string(), ""),
+    }
+}
+
+fn format_station(station: &RadioConfig) -> String {
+    let mut line = String::new();
+    if station.favourite {
+        line.push_str("* ");
+    }
+    let bare = !station.name.is_empty()
+        && !station.name.starts_with('*')
+        && !station
+            .name
+            .contains(|c: char| c.is_whitespace() || c == '"' || c == '\\' || c == '#');
+    if bare {
+        line.push_str(&station.name);
+    } else {
+        line.push('"');
+        for c in station.name.chars() {
+            if c == '"' || c == '\\' {
+                line.push('\\');
+            }
+            line.push(c);
+        }
+        line.push('"');
+    }
+    line.push(' ');
+    line.push_str(&station.url);
+    if !station.genres.is_empty() {
+        line.push(' ');
+        line.push_str(&station.genres.join(", "));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn station(name: &str, genres: &[&str]) -> RadioConfig {
+        RadioConfig {
+            name: name.to_string(),
+            url: "http://example.com/stream".to_string(),
+            genres: genres.iter().map(|genre| genre.to_string()).collect(),
+            favourite: true,
+            comments: vec![],
+        }
+    }
+
+    #[test]
+    fn multi_word_genres_survive_a_save() {
+        let saved = station("BBC Radio 3", &["Classic Rock", "Jazz", "Drum and Bass"]);
+
+        let line = format_station(&saved);
+        let parsed = parse_station(&line).unwrap();
+
+        assert_eq!(
+            line,
+            "* \"BBC Radio 3\" http://example.com/stream Classic Rock, Jazz, Drum and Bass"
+        );
+        assert_eq!(parsed.name, saved.name);
+        assert_eq!(parsed.url, saved.url);
+        assert_eq!(parsed.genres, saved.genres);
+        assert!(parsed.favourite);
+    }
+
+    #[test]
+    fn genres_are_trimmed_and_empty_ones_dropped() {
+        let parsed = parse_station("Radio http://x  Pop ,, Indie Rock ,").unwrap();
+
+        assert_eq!(parsed.genres, ["Pop", "Indie Rock"]);
+    }
+
+    #[test]
+    fn station_without_genres_has_none() {
+        let parsed = parse_station("Radio http://x").unwrap();
+
+        assert!(parsed.genres.is_empty());
+        assert_eq!(format_station(&parsed), "Radio http://x");
+    }
+}
@@ -0,0 +1,96 @@
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState},
+    Frame,
+};
+
+use crate::{
+    app::App,
+    media::device::{list_devices, OutputDevice},
+};
+
+pub struct DevicePicker {
+    pub devices: Vec<OutputDevice>,
+    pub index: ListState,
+}
+
+impl DevicePicker {
+    pub fn new() -> Self {
+        Self {
+            devices: vec![],
+            index: ListState::default(),
+        }
+    }
+
+    /// Lists the devices again, starting on the one in use.
+    pub fn refresh(&mut self, current: Option<&str>) {
+        self.devices = list_devices();
+        // the first entry stands for the system default
+        let selected = current.and_then(|id| self.devices.iter().position(|d| d.id() == id));
+        self.index.select(Some(selected.map_or(0, |i| i + 1)));
+    }
+
+    /// The device id for the selected entry, `None` meaning the system default.
+    pub fn selected(&self) -> Option<String> {
+        let index = self.index.selected()?.checked_sub(1)?;
+        self.devices.get(index).map(|d| d.id())
+    }
+}
+
+pub fn draw_device_picker<B>(app: &mut App, frame: &mut Frame<B>, area: Rect)
+where
+    B: Backend,
+{
+    let area = centered(area, 60, 50);
+    let current = app.player.state().output_device.as_deref();
+    let mut items = vec![ListItem::new(match current {
+        None => "* System default",
+        Some(_) => "  System default",
+    })];
+    for device in &app.device_picker.devices {
+        let id = device.id();
+        let mark = if current == Some(id.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        items.push(ListItem::new(format!("{} {}", mark, id)));
+    }
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Output device")
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(Color::Cyan))
+                .title_alignment(Alignment::Center),
+        )
+        .highlight_style(Style::default().bg(Color::Cyan))
+        .highlight_symbol("> ");
+
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut app.device_picker.index);
+}
+
+/// The middle `width` by `height` percent of `area`, for a popup.
+pub fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - height) / 2),
+            Constraint::Percentage(height),
+            Constraint::Percentage((100 - height) / 2),
+        ])
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - width) / 2),
+            Constraint::Percentage(width),
+            Constraint::Percentage((100 - width) / 2),
+        ])
+        .split(vertical[1])[1]
+}
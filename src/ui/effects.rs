@@ -1,4 +1,6 @@
-use rand::Rng;
+use std::f32::consts::PI;
+
+use rustfft::{num_complex::Complex, FftPlanner};
 use tui::{
     backend::Backend,
     layout::Rect,
@@ -7,31 +9,123 @@ use tui::{
     Frame,
 };
 
-use crate::{app::App, media::player::Player};
+use crate::{app::App, media::spectrum::WINDOW};
+
+const MIN_FREQUENCY: f32 = 40.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+/// Levels below this many dB under full scale draw as empty bars.
+const FLOOR_DB: f32 = 60.0;
+/// How much of the previous level is kept each frame.
+const SMOOTHING: f32 = 0.5;
+/// How far a bar may drop per frame, as a fraction of the full height.
+const FALLOFF: f32 = 0.04;
+const SCALE: u64 = 1000;
+
+pub struct Visualizer {
+    planner: FftPlanner<f32>,
+    window: Vec<f32>,
+    levels: Vec<f32>,
+    bars: Vec<f32>,
+}
+
+impl Visualizer {
+    pub fn new() -> Self {
+        Self {
+            planner: FftPlanner::new(),
+            window: (0..WINDOW)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / WINDOW as f32).cos())
+                .collect(),
+            levels: vec![],
+            bars: vec![],
+        }
+    }
+
+    /// Feeds the latest samples through the FFT and moves `count` bars toward
+    /// the level of their log-spaced band.
+    fn update(&mut self, samples: &[f32], sample_rate: u32, count: usize) {
+        self.levels.resize(count, 0.0);
+        self.bars.resize(count, 0.0);
+        let targets = if samples.len() < WINDOW || sample_rate == 0 {
+            vec![0.0; count]
+        } else {
+            self.bands(&samples[samples.len() - WINDOW..], sample_rate, count)
+        };
+        for ((level, bar), target) in self.levels.iter_mut().zip(&mut self.bars).zip(targets) {
+            *level = *level * SMOOTHING + target * (1.0 - SMOOTHING);
+            *bar = level.max(*bar - FALLOFF);
+        }
+    }
+
+    fn bands(&mut self, samples: &[f32], sample_rate: u32, count: usize) -> Vec<f32> {
+        let mut buffer: Vec<Complex<f32>> = samples
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, w)| Complex::new(sample * w, 0.0))
+            .collect();
+        self.planner.plan_fft_forward(WINDOW).process(&mut buffer);
+        // a full-scale sine peaks at 1.0 once the window's gain is taken out
+        let norm = 2.0 / self.window.iter().sum::<f32>();
+        let magnitudes: Vec<f32> = buffer[..WINDOW / 2]
+            .iter()
+            .map(|c| c.norm() * norm)
+            .collect();
+
+        let bin_width = sample_rate as f32 / WINDOW as f32;
+        let top = MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+        let edge =
+            |band: usize| MIN_FREQUENCY * (top / MIN_FREQUENCY).powf(band as f32 / count as f32);
+        (0..count)
+            .map(|band| {
+                let (low, high) = (edge(band), edge(band + 1));
+                let first = (low / bin_width).ceil() as usize;
+                let last = (high / bin_width).floor() as usize;
+                let peak = if first <= last {
+                    magnitudes[first.min(WINDOW / 2 - 1)..=last.min(WINDOW / 2 - 1)]
+                        .iter()
+                        .fold(0f32, |max, m| max.max(*m))
+                } else {
+                    // narrower than a bin, so use the one its centre falls in
+                    let centre = ((low * high).sqrt() / bin_width).round() as usize;
+                    magnitudes[centre.min(WINDOW / 2 - 1)]
+                };
+                let db = 20.0 * peak.max(1e-9).log10();
+                ((db + FLOOR_DB) / FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
 
 pub fn draw_chart_effects<B>(app: &mut App, frame: &mut Frame<B>, area: Rect)
 where
     B: Backend,
 {
-    let player = &mut app.player;
-
-    let mut rng = rand::thread_rng();
-    let mut cols = vec![];
-    for _ in 0..20 {
-        let mut i = rng.gen_range(0..10);
-        if !player.is_playing() {
-            i = 0;
-        }
-        cols.push(("_", i));
-    }
+    let (samples, sample_rate) = if app.player.state().is_playing {
+        app.player.spectrum().snapshot()
+    } else {
+        (vec![], 0)
+    };
+    // bars are two cells wide with a one cell gap
+    let count = (area.width.saturating_sub(2) as usize + 1) / 3;
+    app.visualizer.update(&samples, sample_rate, count.max(1));
+
+    let cols: Vec<(&str, u64)> = app
+        .visualizer
+        .bars
+        .iter()
+        .map(|bar| {
+            let value = (bar * SCALE as f32) as u64;
+            // small values would print their number inside the bar
+            ("", if value < 10 { 0 } else { value })
+        })
+        .collect();
     let items = BarChart::default()
-        .bar_width(4)
+        .bar_width(2)
         .bar_gap(1)
         .bar_style(Style::default().fg(Color::Cyan).bg(Color::Black))
         .data(&cols)
         .value_style(Style::default().add_modifier(Modifier::ITALIC))
         .label_style(Style::default().add_modifier(Modifier::ITALIC))
-        .max(10)
+        .max(SCALE)
         .block(
             Block::default()
                 .borders(Borders::ALL)
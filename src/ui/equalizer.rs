@@ -0,0 +1,82 @@
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, BorderType, Borders, Paragraph},
+    Frame,
+};
+
+use crate::{
+    app::{ActiveModules, App},
+    media::equalizer::{FREQUENCIES, MAX_GAIN, PRESETS},
+};
+
+pub struct EqualizerController {
+    pub band: usize,
+}
+
+/// The preset matching `gains`, if any.
+pub fn preset_index(gains: &[f32]) -> Option<usize> {
+    PRESETS.iter().position(|(_, preset)| preset == gains)
+}
+
+pub fn draw_equalizer<B>(app: &mut App, frame: &mut Frame<B>, area: Rect)
+where
+    B: Backend,
+{
+    let gains = app.player.state().equalizer;
+    let title = match preset_index(&gains) {
+        Some(index) => format!("EQ {}", PRESETS[index].0),
+        None => "EQ Custom".to_string(),
+    };
+    let active = app.active_modules == ActiveModules::Equalizer;
+
+    // "16k " label, a meter centred on 0 dB, then " +12"
+    let half = (area.width.saturating_sub(2 + 4 + 4) / 2).max(1) as usize;
+    let mut lines = vec![];
+    for (band, (frequency, gain)) in FREQUENCIES.iter().zip(gains.iter()).enumerate() {
+        let label = if *frequency >= 1000.0 {
+            format!("{:>3}k", frequency / 1000.0)
+        } else {
+            format!("{:>4}", frequency)
+        };
+        let filled = ((gain.abs() / MAX_GAIN) * half as f32).round() as usize;
+        let (left, right) = if *gain < 0.0 {
+            (
+                format!("{}{}", "─".repeat(half - filled), "█".repeat(filled)),
+                "─".repeat(half),
+            )
+        } else {
+            (
+                "─".repeat(half),
+                format!("{}{}", "█".repeat(filled), "─".repeat(half - filled)),
+            )
+        };
+        let style = if active && band == app.equalizer.band {
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        lines.push(Spans::from(vec![
+            Span::styled(label, style),
+            Span::raw(" "),
+            Span::styled(format!("{}│{}", left, right), style),
+            Span::styled(format!("{:>+4.0}", gain), style),
+        ]));
+    }
+
+    let mut blck = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_type(BorderType::Rounded)
+        .title_alignment(Alignment::Center);
+
+    if active {
+        blck = blck.border_style(Style::default().fg(Color::Cyan));
+    }
+
+    frame.render_widget(Paragraph::new(lines).block(blck), area);
+}
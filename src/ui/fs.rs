@@ -46,7 +46,10 @@ impl FsExplorer {
             dirs: vec![],
             index: list_state,
             on_error_msg_callback: callback,
-            accept_suffix: vec!["mp3", "wav", "flac", "ts"],
+            accept_suffix: vec![
+                "mp3", "wav", "flac", "ts", "ogg", "oga", "opus", "m4a", "mp4", "aac", "m3u",
+                "m3u8",
+            ],
         };
         let (dirs, files) = exp.visit_dir(path_str)?;
         exp.files = files;
@@ -1,9 +1,10 @@
 pub enum EventType {
-    Player,
     Radio,
 }
 
+pub mod device;
 pub mod effects;
+pub mod equalizer;
 pub mod fs;
 pub mod music_board;
 pub mod play_list;
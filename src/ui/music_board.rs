@@ -3,14 +3,17 @@ use tui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     symbols,
-    text::Spans,
+    text::{Span, Spans},
     widgets::{Block, BorderType, Borders, LineGauge, ListState, Paragraph},
     Frame,
 };
 
-use crate::{app::App, media::player::Player};
+use crate::{app::App, media::player::RepeatMode};
 
-use super::{effects::draw_chart_effects, play_list::draw_play_list, progress::draw_progress};
+use super::{
+    effects::draw_chart_effects, equalizer::draw_equalizer, play_list::draw_play_list,
+    progress::draw_progress,
+};
 
 pub struct MusicController {
     pub state: ListState,
@@ -33,11 +36,16 @@ where
 
     let mid_layout_chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
+        .constraints([
+            Constraint::Percentage(45),
+            Constraint::Percentage(25),
+            Constraint::Percentage(30),
+        ])
         .split(main_layout_chunks[1]);
 
     draw_chart_effects(app, frame, mid_layout_chunks[0]);
-    draw_play_list(app, frame, mid_layout_chunks[1]);
+    draw_equalizer(app, frame, mid_layout_chunks[1]);
+    draw_play_list(app, frame, mid_layout_chunks[2]);
     draw_progress(app, frame, main_layout_chunks[2]);
 }
 
@@ -45,15 +53,29 @@ pub fn draw_header<B>(app: &mut App, frame: &mut Frame<B>, area: Rect)
 where
     B: Backend,
 {
-    let player = &app.player;
+    let player = app.player.state();
     let main_layout_chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
         .split(area);
 
     let playing_text;
-    if let Some(item) = player.playing_song() {
-        playing_text = String::from(item.name.as_str());
+    if let Some(item) = player.playing_song().filter(|item| item.stream.is_some()) {
+        playing_text = format!(
+            "{} · {} · {:.1}kHz {}ch",
+            item.title.as_deref().unwrap_or("Live"),
+            item.name,
+            item.sample_rate as f32 / 1000.0,
+            item.channels
+        );
+    } else if let Some(item) = player.playing_song() {
+        playing_text = format!(
+            "{} · {:.1}kHz {}ch {}kbps",
+            item.name,
+            item.sample_rate as f32 / 1000.0,
+            item.channels,
+            item.bitrate
+        );
     } else {
         playing_text = String::from("None");
     }
@@ -63,7 +85,11 @@ where
             Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
-                .title("Now playing")
+                .title(if player.has_output {
+                    format!("Now playing {:.2}x", player.rate)
+                } else {
+                    "No audio device".to_string()
+                })
                 .title_alignment(Alignment::Center),
         )
         .style(Style::default().add_modifier(Modifier::SLOW_BLINK));
@@ -73,7 +99,7 @@ where
         .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
         .split(main_layout_chunks[0]);
 
-    let sound_volume_percentage = app.player.volume();
+    let sound_volume_percentage = player.volume;
     let bar = LineGauge::default()
         .ratio(sound_volume_percentage.into())
         .label("VOL")
@@ -92,19 +118,40 @@ where
 
     frame.render_widget(text, sub_layout[0]);
     frame.render_widget(bar, sub_layout[1]);
-    let mut p = Paragraph::new(vec![Spans::from("▶(s) >>|(n) EXT(q) REM(r)")])
-        .style(Style::default())
-        .alignment(Alignment::Center);
-    if player.is_playing() {
-        p = Paragraph::new(vec![Spans::from("||(s) >>|(n) EXT(q) REM(r)")])
-            .alignment(Alignment::Center);
-    }
+    let play = if player.is_playing { "||(s)" } else { "▶(s)" };
     let blck = Block::default()
         .borders(Borders::ALL)
         .title("Panel")
         .border_type(BorderType::Rounded)
         .title_alignment(Alignment::Center);
 
-    p = p.block(blck);
+    let p = Paragraph::new(vec![Spans::from(vec![
+        Span::raw(format!("|<<(b) {} >>|(n) ±10s(,/.) ", play)),
+        toggle_span("SHUF(z)", player.shuffle),
+        Span::raw(" "),
+        match player.repeat {
+            RepeatMode::Off => toggle_span("RPT(l)", false),
+            RepeatMode::All => toggle_span("RPT(l)", true),
+            RepeatMode::One => toggle_span("RPT1(l)", true),
+        },
+        Span::raw(" "),
+        toggle_span("REC(c)", player.recording.is_some()),
+        Span::raw(" DEV(o) EXT(q) REM(r)"),
+    ])])
+    .alignment(Alignment::Center)
+    .block(blck);
     frame.render_widget(p, main_layout_chunks[1]);
 }
+
+fn toggle_span(label: &str, on: bool) -> Span<'_> {
+    if on {
+        Span::styled(
+            label,
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )
+    } else {
+        Span::styled(label, Style::default().fg(Color::DarkGray))
+    }
+}
@@ -2,25 +2,36 @@ use tui::{
     backend::Backend,
     layout::{Alignment, Rect},
     style::{Color, Style},
-    widgets::{Block, BorderType, Borders, List, ListItem},
+    text::{Span, Spans},
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, Paragraph},
     Frame,
 };
 
-use crate::app::{ActiveModules, App};
+use crate::{
+    app::{ActiveModules, App},
+    ui::device::centered,
+};
 
 pub fn draw_play_list<B>(app: &mut App, frame: &mut Frame<B>, area: Rect)
 where
     B: Backend,
 {
     let mut items = vec![];
-    let player = &mut app.player;
-    for item in &player.play_list.lists {
-        items.push(ListItem::new(item.name.as_str()));
+    for item in &app.player.state().play_list {
+        let mut list_item = ListItem::new(item.name.as_str());
+        if item.unplayable {
+            list_item = list_item.style(Style::default().fg(Color::DarkGray));
+        }
+        items.push(list_item);
     }
 
+    let title = match app.active_modules {
+        ActiveModules::PlayList => "Playlist (r)emove (w)rite",
+        _ => "Playlist",
+    };
     let mut blck = Block::default()
         .borders(Borders::ALL)
-        .title("Playlist")
+        .title(title)
         .border_type(BorderType::Rounded)
         .title_alignment(Alignment::Center);
 
@@ -33,5 +44,39 @@ where
         .highlight_style(Style::default().bg(Color::Cyan))
         .highlight_symbol("> ");
 
-    frame.render_stateful_widget(list, area, &mut player.play_list.index);
+    frame.render_stateful_widget(list, area, &mut app.play_list_index);
+}
+
+/// Asks for the name to save the playlist as.
+pub fn draw_save_playlist<B>(app: &mut App, frame: &mut Frame<B>, area: Rect)
+where
+    B: Backend,
+{
+    let name = match &app.playlist_name {
+        Some(name) => name,
+        None => return,
+    };
+    let area = centered(area, 60, 20);
+    let lines = vec![
+        Spans::from(Span::raw(format!("{}/", app.fs.current_path))),
+        Spans::from(Span::styled(
+            format!("{}_", name),
+            Style::default().fg(Color::Cyan),
+        )),
+        Spans::from(""),
+        Spans::from(Span::styled(
+            "Enter save · Esc cancel · .m3u8 is added if missing",
+            Style::default().fg(Color::DarkGray),
+        )),
+    ];
+    let p = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Save playlist")
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title_alignment(Alignment::Center),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(p, area);
 }
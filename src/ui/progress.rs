@@ -7,31 +7,47 @@ use tui::{
     Frame,
 };
 
-use crate::{app::App, media::player::Player};
+use crate::app::App;
 
 pub fn draw_progress<B>(app: &mut App, frame: &mut Frame<B>, area: Rect)
 where
     B: Backend,
 {
-    let player = &app.player;
+    let player = app.player.state();
 
-    let current_time = player.current_time;
-    let total_time = player.total_time;
+    let (current_time, total_time) = player.progress;
 
-    let minute_mins = current_time.as_secs() / 60;
-    let minute_secs = current_time.as_secs() % 60;
+    let minute_mins = current_time as u64 / 60;
+    let minute_secs = current_time as u64 % 60;
 
-    let total_mins = total_time.as_secs() / 60;
-    let total_secs = total_time.as_secs() % 60;
+    let total_mins = total_time as u64 / 60;
+    let total_secs = total_time as u64 % 60;
     let mut percent = 0.0;
-    if total_time.as_secs() != 0 {
-        percent = if player.is_playing() {
-            current_time.as_secs_f64() / total_time.as_secs_f64()
+    // a paused track keeps its place on the bar
+    if total_time > 0.0 && player.has_track {
+        percent = (current_time / total_time).min(1.0) as f64;
+    }
+    let live = player
+        .playing_song()
+        .is_some_and(|song| song.stream.is_some());
+    let (behind, kept) = player.time_shift;
+    let s = if player.has_track && live {
+        // the bar spans the audio kept, the live edge at its end
+        percent = if kept > 0.0 {
+            (1.0 - behind / kept).clamp(0.0, 1.0) as f64
         } else {
-            0.0
+            1.0
         };
-    }
-    let s = if player.is_playing() {
+        if behind < 1.0 {
+            format!("{:0>2}:{:0>2} / LIVE", minute_mins, minute_secs)
+        } else {
+            format!(
+                "-{:0>2}:{:0>2} behind LIVE",
+                behind as u64 / 60,
+                behind as u64 % 60
+            )
+        }
+    } else if player.has_track {
         format!(
             "{:0>2}:{:0>2} / {:0>2}:{:0>2}",
             minute_mins, minute_secs, total_mins, total_secs
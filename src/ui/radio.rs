@@ -1,84 +1,247 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    vec,
-};
-
 use tui::{
     backend::Backend,
     layout::{Alignment, Rect},
-    style::{Color, Style},
-    widgets::{Block, BorderType, Borders, List, ListItem, ListState},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState, Paragraph},
     Frame,
 };
 
-use crate::app::App;
+use crate::{
+    app::{ActiveModules, App},
+    media::{
+        engine::PlayerState,
+        media::{Media, Source},
+    },
+    stations::StationFile,
+};
+
+use super::device::centered;
 
 #[derive(Clone)]
 pub struct RadioConfig {
     pub name: String,
     pub url: String,
+    pub genres: Vec<String>,
+    pub favourite: bool,
+    /// Comment lines above the station in `radio.ini`.
+    pub comments: Vec<String>,
+}
+
+impl RadioConfig {
+    /// The station as something to play, HLS if its url is a playlist.
+    pub fn media(&self) -> Media {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        let src = if path.ends_with(".m3u8") {
+            Source::M3u8(self.clone())
+        } else {
+            Source::Http(self.clone())
+        };
+        Media { src }
+    }
 }
 
 pub struct RadioExplorer {
-    pub radios: Vec<RadioConfig>,
+    pub stations: StationFile,
     pub index: ListState,
+    /// The url of the station asked for that the player hasn't started yet.
+    pub connecting: Option<String>,
+    pub editor: Option<StationEditor>,
 }
 
 impl RadioExplorer {
-    pub fn new() -> Self {
-        let mut config_dir = dirs::config_dir().unwrap();
-        let mut configs = vec![];
-        config_dir.push("Wy-Media");
-        std::fs::create_dir_all(config_dir.clone()).unwrap();
-        config_dir.push("radio.ini");
-        let f: File;
-        if !config_dir.as_path().exists() {
-            File::create(config_dir.clone()).unwrap();
-        }
-        f = File::open(config_dir).unwrap();
-        let reader = BufReader::new(f);
-        let mut lines = reader.lines().map(|i| i.unwrap());
-        while let Some(line) = lines.next() {
-            if line.is_empty() {
-                continue;
-            }
-            let radio_bean: Vec<_> = line.split(' ').collect();
-            let config = RadioConfig {
-                name: radio_bean[0].to_string(),
-                url: radio_bean[1].to_string(),
-            };
-            configs.push(config);
-        }
+    pub fn new(stations: StationFile) -> Self {
         let mut state = ListState::default();
         state.select(Some(0));
         Self {
-            radios: configs,
+            stations,
             index: state,
+            connecting: None,
+            editor: None,
+        }
+    }
+
+    pub fn selected(&self) -> Option<&RadioConfig> {
+        self.stations.stations.get(self.index.selected()?)
+    }
+
+    /// Stops showing a station as connecting once the player has it going.
+    pub fn update(&mut self, state: &PlayerState) {
+        if self.connecting.is_some() && self.connecting.as_deref() == playing_url(state) {
+            self.connecting = None;
+        }
+    }
+
+    /// What the player is doing with `radio`, if anything.
+    fn status(&self, radio: &RadioConfig, state: &PlayerState) -> Option<&'static str> {
+        if self.connecting.as_deref() == Some(radio.url.as_str()) {
+            Some("connecting")
+        } else if playing_url(state) != Some(radio.url.as_str()) {
+            None
+        } else if !state.is_playing {
+            Some("paused")
+        } else if state.buffering {
+            Some("buffering")
+        } else {
+            Some("connected")
+        }
+    }
+}
+
+/// The form for adding a station or changing one.
+pub struct StationEditor {
+    /// The name, url and genres as typed.
+    pub fields: [String; 3],
+    pub field: usize,
+    /// The station being changed, `None` when adding one.
+    pub editing: Option<usize>,
+}
+
+const FIELD_NAMES: [&str; 3] = ["Name", "URL", "Genres"];
+
+impl StationEditor {
+    pub fn new() -> Self {
+        Self {
+            fields: Default::default(),
+            field: 0,
+            editing: None,
         }
     }
+
+    pub fn edit(index: usize, station: &RadioConfig) -> Self {
+        Self {
+            fields: [
+                station.name.clone(),
+                station.url.clone(),
+                station.genres.join(", "),
+            ],
+            field: 0,
+            editing: Some(index),
+        }
+    }
+
+    /// The station the form describes, or what is wrong with it.
+    pub fn station(&self) -> Result<RadioConfig, &'static str> {
+        let name = self.fields[0].trim();
+        let url = self.fields[1].trim();
+        if name.is_empty() {
+            return Err("A station needs a name");
+        }
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err("A station url starts with http:// or https://");
+        }
+        if url.contains(char::is_whitespace) {
+            return Err("A station url can't have spaces in it");
+        }
+        Ok(RadioConfig {
+            name: name.to_string(),
+            url: url.to_string(),
+            genres: self.fields[2]
+                .split(',')
+                .map(|genre| genre.trim().to_string())
+                .filter(|genre| !genre.is_empty())
+                .collect(),
+            favourite: false,
+            comments: vec![],
+        })
+    }
+}
+
+/// The url of the station playing, if a station is.
+fn playing_url(state: &PlayerState) -> Option<&str> {
+    state
+        .playing_song()
+        .filter(|song| song.stream.is_some() && state.has_track)
+        .map(|song| song.path.as_str())
 }
 
-#[allow(dead_code)]
 pub fn draw_radio_list<B>(app: &mut App, frame: &mut Frame<B>, area: Rect)
 where
     B: Backend,
 {
     let fs = &mut app.radio_fs;
+    let state = app.player.state();
     let mut item_vec = vec![];
-    for radio in &fs.radios {
-        item_vec.push(ListItem::new(radio.name.as_str()));
+    for radio in &fs.stations.stations {
+        let mark = if radio.favourite { "★ " } else { "  " };
+        let status = fs.status(radio, state);
+        let mut spans = vec![Span::raw(format!("{}{}", mark, radio.name))];
+        if let Some(status) = status {
+            spans.push(Span::raw(format!(" ({})", status)));
+        }
+        if !radio.genres.is_empty() {
+            spans.push(Span::styled(
+                format!(" [{}]", radio.genres.join(", ")),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        let mut item = ListItem::new(Spans::from(spans));
+        if status.is_some() {
+            item = item.style(Style::default().fg(Color::Cyan));
+        }
+        item_vec.push(item);
+    }
+    let active = app.active_modules == ActiveModules::Radio;
+    let mut blck = Block::default()
+        .borders(Borders::all())
+        .title(if active {
+            "Radio (a)dd (e)dit (d)el (f)av J/K"
+        } else {
+            "Radio List"
+        })
+        .border_type(BorderType::Rounded)
+        .title_alignment(Alignment::Center);
+
+    if active {
+        blck = blck.border_style(Style::default().fg(Color::Cyan));
     }
-    let list = List::new(item_vec.as_ref())
-        .block(
-            Block::default()
-                .borders(Borders::all())
-                .title("Radio List")
-                .border_type(BorderType::Rounded)
-                .border_style(Style::default().fg(Color::Cyan))
-                .title_alignment(Alignment::Center),
-        )
+
+    let list = List::new(item_vec)
+        .block(blck)
         .highlight_style(Style::default().bg(Color::Cyan))
         .highlight_symbol("> ");
     frame.render_stateful_widget(list, area, &mut fs.index);
 }
+
+pub fn draw_station_editor<B>(app: &mut App, frame: &mut Frame<B>, area: Rect)
+where
+    B: Backend,
+{
+    let editor = match &app.radio_fs.editor {
+        Some(editor) => editor,
+        None => return,
+    };
+    let area = centered(area, 60, 30);
+    let mut lines = vec![];
+    for (i, (name, value)) in FIELD_NAMES.iter().zip(editor.fields.iter()).enumerate() {
+        let mut style = Style::default();
+        let mut value = value.clone();
+        if i == editor.field {
+            style = style.fg(Color::Cyan).add_modifier(Modifier::BOLD);
+            value.push('_');
+        }
+        lines.push(Spans::from(vec![
+            Span::styled(format!("{:>7} ", name), style),
+            Span::raw(value),
+        ]));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "Enter save · Esc cancel · Tab next field · genres split by commas",
+        Style::default().fg(Color::DarkGray),
+    )));
+
+    let p = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(match editor.editing {
+                Some(_) => "Edit station",
+                None => "Add station",
+            })
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title_alignment(Alignment::Center),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(p, area);
+}